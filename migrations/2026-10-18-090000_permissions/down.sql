@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+drop table role_permissions;
+drop table permissions;
\ No newline at end of file
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+create table permissions (
+  id            uuid primary key default gen_random_uuid(),
+  name          varchar(250)    not null,
+  code          varchar(250)    not null unique,
+  description   text            not null,
+  created_at    timestamptz     not null default now(),
+  updated_at    timestamptz     not null default now()
+);
+
+create table role_permissions (
+  role_id       uuid            not null references roles(id) on delete cascade,
+  permission_id uuid            not null references permissions(id) on delete cascade,
+  created_at    timestamptz     not null default now(),
+  primary key (role_id, permission_id)
+);
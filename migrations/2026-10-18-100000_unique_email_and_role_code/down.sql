@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+drop index roles_code_unique;
+drop index users_email_unique;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Uniqueness is case-insensitive and ignores soft-deleted rows, so a deleted
+-- account does not block its address from being registered again.
+create unique index users_email_unique on users (lower(email)) where deleted_at is null;
+create unique index roles_code_unique on roles (lower(code)) where deleted_at is null;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+create table refresh_tokens (
+  id            uuid primary key default gen_random_uuid(),
+  user_id       uuid            not null references users(id) on delete cascade,
+  family_id     uuid            not null,
+  token_hash    varchar(64)     not null unique,
+  expires_at    timestamptz     not null,
+  created_at    timestamptz     not null default now(),
+  used_at       timestamptz,
+  revoked_at    timestamptz
+);
+
+create index refresh_tokens_family_id_idx on refresh_tokens (family_id);
+create index refresh_tokens_user_id_idx on refresh_tokens (user_id);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+drop table password_reset_tokens;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+create table password_reset_tokens (
+  id            uuid primary key default gen_random_uuid(),
+  user_id       uuid            not null references users(id) on delete cascade,
+  token_hash    varchar(64)     not null unique,
+  expires_at    timestamptz     not null,
+  created_at    timestamptz     not null default now(),
+  used_at       timestamptz
+);
+
+create index password_reset_tokens_user_id_idx on password_reset_tokens (user_id);
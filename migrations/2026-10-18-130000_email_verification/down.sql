@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+drop table email_verification_tokens;
+alter table users drop column email_verified_at;
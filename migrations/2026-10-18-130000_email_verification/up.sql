@@ -0,0 +1,14 @@
+-- Your SQL goes here
+alter table users add column email_verified_at timestamptz;
+
+create table email_verification_tokens (
+  id            uuid primary key default gen_random_uuid(),
+  user_id       uuid            not null references users(id) on delete cascade,
+  email         varchar(250)    not null,
+  token_hash    varchar(64)     not null unique,
+  expires_at    timestamptz     not null,
+  created_at    timestamptz     not null default now(),
+  used_at       timestamptz
+);
+
+create index email_verification_tokens_user_id_idx on email_verification_tokens (user_id);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+create table audit_events (
+  id            uuid primary key default gen_random_uuid(),
+  actor_id      uuid,
+  action        varchar(50)     not null,
+  entity_type   varchar(50)     not null,
+  entity_id     uuid            not null,
+  changes       jsonb           not null default '{}',
+  request_id    varchar(100),
+  created_at    timestamptz     not null default now()
+);
+
+create index audit_events_entity_idx on audit_events (entity_type, entity_id, created_at);
+create index audit_events_actor_id_idx on audit_events (actor_id, created_at);
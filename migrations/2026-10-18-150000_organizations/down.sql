@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+drop index audit_events_entity_idx;
+drop index audit_events_actor_id_idx;
+create index audit_events_entity_idx on audit_events (entity_type, entity_id, created_at);
+create index audit_events_actor_id_idx on audit_events (actor_id, created_at);
+
+drop index users_email_unique;
+drop index roles_code_unique;
+create unique index users_email_unique on users (lower(email)) where deleted_at is null;
+create unique index roles_code_unique on roles (lower(code)) where deleted_at is null;
+
+alter table users drop constraint users_role_same_organization;
+alter table roles drop constraint roles_organization_id_id_key;
+
+alter table audit_events drop column organization_id;
+alter table users drop column organization_id;
+alter table roles drop column organization_id;
+drop table organizations;
@@ -0,0 +1,39 @@
+-- Your SQL goes here
+create table organizations (
+  id            uuid primary key default gen_random_uuid(),
+  name          varchar(250)    not null,
+  slug          varchar(100)    not null unique,
+  created_at    timestamptz     not null default now(),
+  updated_at    timestamptz     not null default now()
+);
+
+-- Everything that exists so far belongs to one default organization
+insert into organizations (name, slug) values ('Default', 'default');
+
+alter table roles add column organization_id uuid references organizations(id);
+update roles set organization_id = (select id from organizations where slug = 'default');
+alter table roles alter column organization_id set not null;
+
+alter table users add column organization_id uuid references organizations(id);
+update users set organization_id = (select id from organizations where slug = 'default');
+alter table users alter column organization_id set not null;
+
+alter table audit_events add column organization_id uuid references organizations(id);
+update audit_events set organization_id = (select id from organizations where slug = 'default');
+alter table audit_events alter column organization_id set not null;
+
+-- A user's role must belong to the user's own organization
+alter table roles add constraint roles_organization_id_id_key unique (organization_id, id);
+alter table users add constraint users_role_same_organization
+  foreign key (organization_id, role_id) references roles (organization_id, id);
+
+-- Emails and role codes only need to be unique within an organization
+drop index users_email_unique;
+drop index roles_code_unique;
+create unique index users_email_unique on users (organization_id, lower(email)) where deleted_at is null;
+create unique index roles_code_unique on roles (organization_id, lower(code)) where deleted_at is null;
+
+drop index audit_events_entity_idx;
+drop index audit_events_actor_id_idx;
+create index audit_events_entity_idx on audit_events (organization_id, entity_type, entity_id, created_at);
+create index audit_events_actor_id_idx on audit_events (organization_id, actor_id, created_at);
@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+-- Each user keeps the role assigned to them first; users without any role
+-- cannot be represented and make this migration fail
+alter table users add column role_id uuid;
+update users set role_id = (
+  select user_roles.role_id from user_roles
+  where user_roles.user_id = users.id
+  order by user_roles.created_at, user_roles.role_id
+  limit 1
+);
+alter table users alter column role_id set not null;
+alter table users add constraint users_role_id_fkey foreign key (role_id) references roles(id);
+alter table users add constraint users_role_same_organization
+  foreign key (organization_id, role_id) references roles (organization_id, id);
+
+drop table user_roles;
+alter table users drop constraint users_organization_id_id_key;
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+alter table users add constraint users_organization_id_id_key unique (organization_id, id);
+
+-- Both sides of an assignment belong to the same organization
+create table user_roles (
+  user_id         uuid            not null,
+  role_id         uuid            not null,
+  organization_id uuid            not null references organizations(id),
+  created_at      timestamptz     not null default now(),
+  primary key (user_id, role_id),
+  foreign key (organization_id, user_id) references users (organization_id, id) on delete cascade,
+  foreign key (organization_id, role_id) references roles (organization_id, id) on delete cascade
+);
+create index user_roles_role_id_idx on user_roles (role_id);
+
+insert into user_roles (user_id, role_id, organization_id)
+select id, role_id, organization_id from users;
+
+alter table users drop column role_id;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+alter table roles drop column parent_role_id;
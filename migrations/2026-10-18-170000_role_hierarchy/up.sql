@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- A role inherits every permission of its parent, which lives in the same
+-- organization
+alter table roles add column parent_role_id uuid;
+alter table roles add constraint roles_parent_same_organization
+  foreign key (organization_id, parent_role_id) references roles (organization_id, id);
+alter table roles add constraint roles_parent_not_self check (parent_role_id <> id);
+create index roles_parent_role_id_idx on roles (parent_role_id);
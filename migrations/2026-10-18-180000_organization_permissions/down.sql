@@ -0,0 +1,28 @@
+-- This file should undo anything in `up.sql`
+-- The copies of a code are merged back into the oldest of them
+alter table role_permissions drop constraint role_permissions_role_same_organization;
+alter table role_permissions drop constraint role_permissions_permission_same_organization;
+
+update role_permissions rp
+set permission_id = kept.id
+from permissions copy, (
+  select distinct on (code) id, code from permissions order by code, created_at, id
+) kept
+where copy.id = rp.permission_id
+  and kept.code = copy.code;
+
+delete from permissions p
+where p.id <> (
+  select id from permissions k where k.code = p.code order by created_at, id limit 1
+);
+
+alter table role_permissions drop column organization_id;
+alter table role_permissions add constraint role_permissions_role_id_fkey
+  foreign key (role_id) references roles(id) on delete cascade;
+alter table role_permissions add constraint role_permissions_permission_id_fkey
+  foreign key (permission_id) references permissions(id) on delete cascade;
+
+drop index permissions_code_unique;
+alter table permissions drop constraint permissions_organization_id_id_key;
+alter table permissions drop column organization_id;
+alter table permissions add constraint permissions_code_key unique (code);
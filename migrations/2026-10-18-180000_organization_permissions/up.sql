@@ -0,0 +1,36 @@
+-- Your SQL goes here
+-- Every organization gets its own copy of the permission catalog, and the
+-- roles keep the permissions they had through their organization's copy
+alter table permissions add column organization_id uuid references organizations(id);
+alter table permissions drop constraint permissions_code_key;
+
+insert into permissions (name, code, description, created_at, updated_at, organization_id)
+select p.name, p.code, p.description, p.created_at, p.updated_at, o.id
+from permissions p cross join organizations o
+where p.organization_id is null;
+
+alter table role_permissions add column organization_id uuid;
+
+update role_permissions rp
+set permission_id = copy.id, organization_id = r.organization_id
+from roles r, permissions shared, permissions copy
+where r.id = rp.role_id
+  and shared.id = rp.permission_id
+  and copy.code = shared.code
+  and copy.organization_id = r.organization_id;
+
+delete from permissions where organization_id is null;
+alter table permissions alter column organization_id set not null;
+alter table role_permissions alter column organization_id set not null;
+
+-- Permission codes only need to be unique within an organization
+create unique index permissions_code_unique on permissions (organization_id, lower(code));
+alter table permissions add constraint permissions_organization_id_id_key unique (organization_id, id);
+
+-- Both sides of a grant belong to the same organization
+alter table role_permissions drop constraint role_permissions_role_id_fkey;
+alter table role_permissions drop constraint role_permissions_permission_id_fkey;
+alter table role_permissions add constraint role_permissions_role_same_organization
+  foreign key (organization_id, role_id) references roles (organization_id, id) on delete cascade;
+alter table role_permissions add constraint role_permissions_permission_same_organization
+  foreign key (organization_id, permission_id) references permissions (organization_id, id) on delete cascade;
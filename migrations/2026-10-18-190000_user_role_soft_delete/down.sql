@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+delete from user_roles where deleted_at is not null;
+alter table user_roles drop column deleted_at;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Assignments are soft-deleted along with a role deleted by force, so
+-- restoring the role can hand it back to the same users
+alter table user_roles add column deleted_at timestamptz;
@@ -0,0 +1,57 @@
+use serde::Deserialize;
+
+pub const DEFAULT_ACCESS_TOKEN_TTL: i64 = 900;
+pub const DEFAULT_REFRESH_TOKEN_TTL: i64 = 30 * 24 * 60 * 60;
+pub const DEFAULT_PASSWORD_RESET_TTL: i64 = 60 * 60;
+pub const DEFAULT_EMAIL_VERIFICATION_TTL: i64 = 24 * 60 * 60;
+pub const DEFAULT_BCRYPT_COST: u32 = 10;
+/// Accepted token lifetimes, in seconds. Long-lived tokens widen the window
+/// in which a leaked one can be used, so each kind is capped.
+pub const ACCESS_TOKEN_TTL_RANGE: std::ops::RangeInclusive<i64> = 1..=24 * 60 * 60;
+pub const REFRESH_TOKEN_TTL_RANGE: std::ops::RangeInclusive<i64> = 1..=365 * 24 * 60 * 60;
+pub const PASSWORD_RESET_TTL_RANGE: std::ops::RangeInclusive<i64> = 1..=24 * 60 * 60;
+pub const EMAIL_VERIFICATION_TTL_RANGE: std::ops::RangeInclusive<i64> = 1..=30 * 24 * 60 * 60;
+/// Bounds accepted by bcrypt for the cost factor.
+pub const BCRYPT_COST_RANGE: std::ops::RangeInclusive<u32> = 4..=31;
+
+/// Token and password hashing settings, the `[auth]` section of the
+/// configuration.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// HMAC secret used to sign access tokens.
+    pub jwt_secret: String,
+    /// Lifetime of access tokens, in seconds.
+    pub access_token_ttl: i64,
+    /// Lifetime of refresh tokens, in seconds.
+    pub refresh_token_ttl: i64,
+    /// Lifetime of password reset tokens, in seconds.
+    pub password_reset_ttl: i64,
+    /// Page of the client application that completes a password reset; the
+    /// token is appended as `?token=...`.
+    pub password_reset_url: String,
+    /// Lifetime of email verification tokens, in seconds.
+    pub email_verification_ttl: i64,
+    /// Page of the client application that confirms an email address; the
+    /// token is appended as `?token=...`.
+    pub email_verification_url: String,
+    /// Refuse to log in users whose email address is not verified.
+    pub require_verified_email: bool,
+    pub bcrypt_cost: u32,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: String::new(),
+            access_token_ttl: DEFAULT_ACCESS_TOKEN_TTL,
+            refresh_token_ttl: DEFAULT_REFRESH_TOKEN_TTL,
+            password_reset_ttl: DEFAULT_PASSWORD_RESET_TTL,
+            password_reset_url: "http://localhost:3000/reset-password".to_string(),
+            email_verification_ttl: DEFAULT_EMAIL_VERIFICATION_TTL,
+            email_verification_url: "http://localhost:3000/verify-email".to_string(),
+            require_verified_email: false,
+            bcrypt_cost: DEFAULT_BCRYPT_COST,
+        }
+    }
+}
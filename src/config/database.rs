@@ -1,15 +1,125 @@
+use crate::repositories::RepositoryError;
+use diesel::connection::{AnsiTransactionManager, TransactionManager};
 use diesel::pg::PgConnection;
-use diesel::r2d2::{ConnectionManager, Pool};
-use dotenvy::dotenv;
-use std::env;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 
-pub fn establish_connection() -> DbPool {
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
+/// Connection pool settings, the `[database]` section of the configuration.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    /// Idle connections kept open; defaults to `max_connections`.
+    pub min_idle: Option<u32>,
+    /// Seconds to wait for a free connection before giving up.
+    pub connection_timeout: u64,
+    /// Seconds after which an idle connection is closed; 0 keeps them open.
+    pub idle_timeout: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            max_connections: 10,
+            min_idle: None,
+            connection_timeout: 30,
+            idle_timeout: 600,
+        }
+    }
+}
+
+pub fn establish_connection(config: &DatabaseConfig) -> Result<DbPool, PoolError> {
+    let manager = ConnectionManager::<PgConnection>::new(&config.url);
     Pool::builder()
+        .max_size(config.max_connections)
+        .min_idle(config.min_idle)
+        .connection_timeout(Duration::from_secs(config.connection_timeout))
+        .idle_timeout((config.idle_timeout > 0).then(|| Duration::from_secs(config.idle_timeout)))
         .build(manager)
-        .expect("Failed to create DB pool")
+}
+
+/// Handle used by repositories to run Diesel queries.
+///
+/// Diesel and r2d2 are blocking, so every query runs on Tokio's blocking
+/// thread pool instead of stalling the async workers that serve requests.
+#[derive(Clone)]
+pub struct Database {
+    pool: DbPool,
+    /// Set on handles returned by [`Database::begin`], whose queries all run
+    /// on this connection inside its open transaction.
+    transaction: Option<Arc<Mutex<PooledConnection<ConnectionManager<PgConnection>>>>>,
+}
+
+impl Database {
+    pub fn new(pool: DbPool) -> Self {
+        Database {
+            pool,
+            transaction: None,
+        }
+    }
+
+    /// Checks out a connection and runs `f` with it off the async executor.
+    /// Failing to get a connection in time yields
+    /// [`RepositoryError::Unavailable`].
+    pub async fn run<T, F>(&self, f: F) -> Result<T, RepositoryError>
+    where
+        F: FnOnce(&mut PgConnection) -> diesel::QueryResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let transaction = self.transaction.clone();
+        tokio::task::spawn_blocking(move || match transaction {
+            Some(conn) => {
+                let mut conn = conn.lock().map_err(|_| {
+                    RepositoryError::Database("Transaction connection poisoned".to_string())
+                })?;
+                f(&mut conn).map_err(RepositoryError::from)
+            }
+            None => {
+                let mut conn = pool
+                    .get()
+                    .map_err(|e| RepositoryError::Unavailable(e.to_string()))?;
+                f(&mut conn).map_err(RepositoryError::from)
+            }
+        })
+        .await
+        .map_err(|e| RepositoryError::Database(format!("Database task failed: {}", e)))?
+    }
+
+    /// Checks out a connection and opens a transaction on it. Repositories
+    /// built on the returned handle share that transaction until
+    /// [`Database::commit`] or [`Database::rollback`]. A handle dropped with
+    /// the transaction still open has its connection discarded by the pool,
+    /// which rolls the transaction back.
+    pub async fn begin(&self) -> Result<Database, RepositoryError> {
+        let pool = self.pool.clone();
+        let conn = tokio::task::spawn_blocking(move || {
+            let mut conn = pool
+                .get()
+                .map_err(|e| RepositoryError::Unavailable(e.to_string()))?;
+            AnsiTransactionManager::begin_transaction(&mut *conn)?;
+            Ok::<_, RepositoryError>(conn)
+        })
+        .await
+        .map_err(|e| RepositoryError::Database(format!("Database task failed: {}", e)))??;
+
+        Ok(Database {
+            pool: self.pool.clone(),
+            transaction: Some(Arc::new(Mutex::new(conn))),
+        })
+    }
+
+    pub async fn commit(&self) -> Result<(), RepositoryError> {
+        self.run(AnsiTransactionManager::commit_transaction).await
+    }
+
+    pub async fn rollback(&self) -> Result<(), RepositoryError> {
+        self.run(AnsiTransactionManager::rollback_transaction).await
+    }
 }
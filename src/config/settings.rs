@@ -0,0 +1,476 @@
+use crate::config::auth::{
+    ACCESS_TOKEN_TTL_RANGE, AuthConfig, BCRYPT_COST_RANGE, EMAIL_VERIFICATION_TTL_RANGE,
+    PASSWORD_RESET_TTL_RANGE, REFRESH_TOKEN_TTL_RANGE,
+};
+use crate::config::database::DatabaseConfig;
+use crate::extractors::{ORGANIZATION_HEADER, REQUEST_ID_HEADER};
+use axum::http::{HeaderValue, Method, header};
+use dotenvy::dotenv;
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing_subscriber::EnvFilter;
+
+/// File read when `CONFIG_FILE` is not set, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Typed application configuration.
+///
+/// Values come from an optional TOML file (`CONFIG_FILE`, or `config.toml`
+/// in the working directory) and are then overridden by environment
+/// variables, so containers can be configured without a file.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub log: LogConfig,
+    pub cors: CorsConfig,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 3000,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// A `tracing` filter such as `info` or `info,tower_http=debug`.
+    pub level: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "info".to_string(),
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn filter(&self) -> EnvFilter {
+        EnvFilter::new(&self.level)
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API from a browser. Empty disables CORS,
+    /// `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+}
+
+impl CorsConfig {
+    pub fn layer(&self) -> CorsLayer {
+        let layer = CorsLayer::new()
+            .allow_methods([
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ])
+            .allow_headers([
+                header::AUTHORIZATION,
+                header::CONTENT_TYPE,
+                header::IF_MATCH,
+                ORGANIZATION_HEADER,
+                REQUEST_ID_HEADER,
+            ])
+            .expose_headers([header::ETAG, REQUEST_ID_HEADER]);
+        if self.allowed_origins.iter().any(|origin| origin == "*") {
+            return layer.allow_origin(AllowOrigin::any());
+        }
+        let origins = self
+            .allowed_origins
+            .iter()
+            .filter_map(|origin| HeaderValue::from_str(origin).ok());
+        layer.allow_origin(AllowOrigin::list(origins))
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    File { path: String, message: String },
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::File { path, message } => {
+                write!(f, "failed to read config file {}: {}", path, message)
+            }
+            ConfigError::Invalid(errors) => {
+                write!(f, "invalid configuration:")?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Settings {
+    /// Loads `.env`, the optional config file and environment overrides, then
+    /// validates the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        dotenv().ok();
+        let env = |key: &str| std::env::var(key).ok();
+        let settings = match env("CONFIG_FILE") {
+            Some(path) => Self::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(DEFAULT_CONFIG_FILE)?
+            }
+            None => Settings::default(),
+        };
+        settings.with_env(env)
+    }
+
+    fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let file_error = |message: String| ConfigError::File {
+            path: path.to_string(),
+            message,
+        };
+        let contents = std::fs::read_to_string(path).map_err(|e| file_error(e.to_string()))?;
+        toml::from_str(&contents).map_err(|e| file_error(e.to_string()))
+    }
+
+    /// Applies overrides from `env` and validates the result, reporting every
+    /// problem at once.
+    fn with_env(mut self, env: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut errors = Vec::new();
+        let mut set = |key: &str, apply: &mut dyn FnMut(&str) -> Result<(), String>| {
+            if let Some(value) = env(key)
+                && let Err(e) = apply(&value)
+            {
+                errors.push(format!("{}: {}", key, e));
+            }
+        };
+
+        set("HOST", &mut |v| assign(&mut self.server.host, v));
+        set("PORT", &mut |v| assign(&mut self.server.port, v));
+        set("DATABASE_URL", &mut |v| {
+            self.database.url = v.to_string();
+            Ok(())
+        });
+        set("DATABASE_MAX_CONNECTIONS", &mut |v| {
+            assign(&mut self.database.max_connections, v)
+        });
+        set("DATABASE_MIN_IDLE", &mut |v| {
+            let mut min_idle = 0;
+            assign(&mut min_idle, v)?;
+            self.database.min_idle = Some(min_idle);
+            Ok(())
+        });
+        set("DATABASE_CONNECTION_TIMEOUT", &mut |v| {
+            assign(&mut self.database.connection_timeout, v)
+        });
+        set("DATABASE_IDLE_TIMEOUT", &mut |v| {
+            assign(&mut self.database.idle_timeout, v)
+        });
+        set("JWT_SECRET", &mut |v| assign(&mut self.auth.jwt_secret, v));
+        set("JWT_ACCESS_TOKEN_TTL", &mut |v| {
+            assign(&mut self.auth.access_token_ttl, v)
+        });
+        set("REFRESH_TOKEN_TTL", &mut |v| {
+            assign(&mut self.auth.refresh_token_ttl, v)
+        });
+        set("PASSWORD_RESET_TTL", &mut |v| {
+            assign(&mut self.auth.password_reset_ttl, v)
+        });
+        set("PASSWORD_RESET_URL", &mut |v| {
+            assign(&mut self.auth.password_reset_url, v)
+        });
+        set("EMAIL_VERIFICATION_TTL", &mut |v| {
+            assign(&mut self.auth.email_verification_ttl, v)
+        });
+        set("EMAIL_VERIFICATION_URL", &mut |v| {
+            assign(&mut self.auth.email_verification_url, v)
+        });
+        set("REQUIRE_VERIFIED_EMAIL", &mut |v| {
+            assign(&mut self.auth.require_verified_email, v)
+        });
+        set("BCRYPT_COST", &mut |v| {
+            assign(&mut self.auth.bcrypt_cost, v)
+        });
+        set("LOG_LEVEL", &mut |v| assign(&mut self.log.level, v));
+        set("CORS_ALLOWED_ORIGINS", &mut |v| {
+            self.cors.allowed_origins = v
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+            Ok(())
+        });
+
+        self.validate(&mut errors);
+        if errors.is_empty() {
+            Ok(self)
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.server.host.trim().is_empty() {
+            errors.push("server.host must not be empty".to_string());
+        }
+        if self.database.url.trim().is_empty() {
+            errors.push("database.url is required (set DATABASE_URL)".to_string());
+        }
+        if self.database.max_connections == 0 {
+            errors.push("database.max_connections must be at least 1".to_string());
+        }
+        if let Some(min_idle) = self.database.min_idle
+            && min_idle > self.database.max_connections
+        {
+            errors.push(format!(
+                "database.min_idle ({}) must not exceed database.max_connections ({})",
+                min_idle, self.database.max_connections
+            ));
+        }
+        if self.database.connection_timeout == 0 {
+            errors.push("database.connection_timeout must be at least 1 second".to_string());
+        }
+        if self.auth.jwt_secret.is_empty() {
+            errors.push("auth.jwt_secret is required (set JWT_SECRET)".to_string());
+        }
+        for (name, ttl, range) in [
+            (
+                "auth.access_token_ttl",
+                self.auth.access_token_ttl,
+                ACCESS_TOKEN_TTL_RANGE,
+            ),
+            (
+                "auth.refresh_token_ttl",
+                self.auth.refresh_token_ttl,
+                REFRESH_TOKEN_TTL_RANGE,
+            ),
+            (
+                "auth.password_reset_ttl",
+                self.auth.password_reset_ttl,
+                PASSWORD_RESET_TTL_RANGE,
+            ),
+            (
+                "auth.email_verification_ttl",
+                self.auth.email_verification_ttl,
+                EMAIL_VERIFICATION_TTL_RANGE,
+            ),
+        ] {
+            if !range.contains(&ttl) {
+                errors.push(format!(
+                    "{} must be between {} and {} seconds",
+                    name,
+                    range.start(),
+                    range.end()
+                ));
+            }
+        }
+        if self.auth.refresh_token_ttl <= self.auth.access_token_ttl {
+            errors.push(
+                "auth.refresh_token_ttl must be longer than auth.access_token_ttl".to_string(),
+            );
+        }
+        if self.auth.password_reset_url.trim().is_empty() {
+            errors.push("auth.password_reset_url must not be empty".to_string());
+        }
+        if self.auth.email_verification_url.trim().is_empty() {
+            errors.push("auth.email_verification_url must not be empty".to_string());
+        }
+        if !BCRYPT_COST_RANGE.contains(&self.auth.bcrypt_cost) {
+            errors.push(format!(
+                "auth.bcrypt_cost must be between {} and {}",
+                BCRYPT_COST_RANGE.start(),
+                BCRYPT_COST_RANGE.end()
+            ));
+        }
+        if let Err(e) = EnvFilter::try_new(&self.log.level) {
+            errors.push(format!("log.level is not a valid filter: {}", e));
+        }
+        for origin in &self.cors.allowed_origins {
+            let valid = origin == "*"
+                || ((origin.starts_with("http://") || origin.starts_with("https://"))
+                    && HeaderValue::from_str(origin).is_ok());
+            if !valid {
+                errors.push(format!("cors.allowed_origins: invalid origin {:?}", origin));
+            }
+        }
+    }
+}
+
+/// Parses an environment value into a setting.
+fn assign<T>(target: &mut T, value: &str) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    *target = value
+        .trim()
+        .parse()
+        .map_err(|e| format!("{} ({:?})", e, value))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    fn errors(result: Result<Settings, ConfigError>) -> Vec<String> {
+        match result {
+            Err(ConfigError::Invalid(errors)) => errors,
+            Err(other) => panic!("unexpected error: {}", other),
+            Ok(_) => panic!("expected invalid configuration"),
+        }
+    }
+
+    const REQUIRED: [(&str, &str); 2] = [
+        ("DATABASE_URL", "postgres://localhost/app"),
+        ("JWT_SECRET", "secret"),
+    ];
+
+    #[test]
+    fn defaults_only_need_database_url_and_secret() {
+        let settings = Settings::default().with_env(env(&REQUIRED)).unwrap();
+
+        assert_eq!(settings.server.address(), "127.0.0.1:3000");
+        assert_eq!(settings.database.max_connections, 10);
+        assert_eq!(settings.auth.bcrypt_cost, 10);
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let file: Settings = toml::from_str(
+            r#"
+            [server]
+            host = "127.0.0.1"
+            port = 8080
+
+            [database]
+            url = "postgres://file/app"
+            max_connections = 4
+
+            [cors]
+            allowed_origins = ["https://file.example.com"]
+            "#,
+        )
+        .unwrap();
+        let vars = [
+            ("HOST", "0.0.0.0"),
+            ("JWT_SECRET", "secret"),
+            (
+                "CORS_ALLOWED_ORIGINS",
+                "https://a.example.com, https://b.example.com",
+            ),
+        ];
+
+        let settings = file.with_env(env(&vars)).unwrap();
+
+        assert_eq!(settings.server.address(), "0.0.0.0:8080");
+        assert_eq!(settings.database.url, "postgres://file/app");
+        assert_eq!(settings.database.max_connections, 4);
+        assert_eq!(
+            settings.cors.allowed_origins,
+            vec!["https://a.example.com", "https://b.example.com"]
+        );
+    }
+
+    #[test]
+    fn unknown_file_keys_are_rejected() {
+        let result = toml::from_str::<Settings>("[server]\nhots = \"0.0.0.0\"\n");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn missing_required_values_are_reported() {
+        let errors = errors(Settings::default().with_env(env(&[])));
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("DATABASE_URL"));
+        assert!(errors[1].contains("JWT_SECRET"));
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported() {
+        let mut vars = REQUIRED.to_vec();
+        vars.extend([
+            ("PORT", "http"),
+            ("DATABASE_MAX_CONNECTIONS", "2"),
+            ("DATABASE_MIN_IDLE", "5"),
+            ("BCRYPT_COST", "40"),
+            ("LOG_LEVEL", "info,=="),
+            ("CORS_ALLOWED_ORIGINS", "example.com"),
+        ]);
+
+        let errors = errors(Settings::default().with_env(env(&vars)));
+
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert!(errors[0].starts_with("PORT:"));
+    }
+
+    #[test]
+    fn token_lifetimes_are_bounded() {
+        let mut vars = REQUIRED.to_vec();
+        vars.extend([
+            ("JWT_ACCESS_TOKEN_TTL", "0"),
+            ("REFRESH_TOKEN_TTL", "31536001"),
+            ("PASSWORD_RESET_TTL", "86401"),
+            ("EMAIL_VERIFICATION_TTL", "9223372036854775807"),
+        ]);
+
+        let errors = errors(Settings::default().with_env(env(&vars)));
+
+        assert_eq!(
+            errors,
+            vec![
+                "auth.access_token_ttl must be between 1 and 86400 seconds",
+                "auth.refresh_token_ttl must be between 1 and 31536000 seconds",
+                "auth.password_reset_ttl must be between 1 and 86400 seconds",
+                "auth.email_verification_ttl must be between 1 and 2592000 seconds",
+            ]
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.extend([
+            ("JWT_ACCESS_TOKEN_TTL", "86400"),
+            ("REFRESH_TOKEN_TTL", "31536000"),
+            ("PASSWORD_RESET_TTL", "86400"),
+            ("EMAIL_VERIFICATION_TTL", "2592000"),
+        ]);
+        assert!(Settings::default().with_env(env(&vars)).is_ok());
+    }
+}
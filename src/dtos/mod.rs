@@ -0,0 +1,2 @@
+pub mod role_dto;
+pub mod user_dto;
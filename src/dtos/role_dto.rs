@@ -0,0 +1,34 @@
+use crate::models::role::Role;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Wire representation of a role.
+#[derive(Debug, Serialize)]
+pub struct RoleResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub code: String,
+    pub description: String,
+    pub organization_id: Uuid,
+    pub parent_role_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+impl From<Role> for RoleResponse {
+    fn from(role: Role) -> Self {
+        RoleResponse {
+            id: role.id,
+            name: role.name,
+            code: role.code,
+            description: role.description,
+            organization_id: role.organization_id,
+            parent_role_id: role.parent_role_id,
+            created_at: role.created_at,
+            updated_at: role.updated_at,
+            deleted_at: role.deleted_at,
+        }
+    }
+}
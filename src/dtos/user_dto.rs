@@ -0,0 +1,70 @@
+use crate::dtos::role_dto::RoleResponse;
+use crate::models::query::UserInclude;
+use crate::models::role::Role;
+use crate::models::user::User;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Wire representation of a user. The password hash is deliberately absent.
+#[derive(Debug, Serialize)]
+pub struct UserResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub role_ids: Vec<Uuid>,
+    pub organization_id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub email_verified_at: Option<NaiveDateTime>,
+    /// The assigned roles themselves, only present when asked for with
+    /// `?include=role`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<RoleResponse>>,
+}
+
+impl UserResponse {
+    /// Builds the response for `user`, who has been assigned `roles`.
+    pub fn new(user: User, roles: &[Role]) -> Self {
+        UserResponse {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            role_ids: roles.iter().map(|role| role.id).collect(),
+            organization_id: user.organization_id,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            deleted_at: user.deleted_at,
+            email_verified_at: user.email_verified_at,
+            roles: None,
+        }
+    }
+
+    /// Like [`Self::new`], also embedding `roles` in the response.
+    pub fn with_roles(user: User, roles: Vec<Role>) -> Self {
+        let mut response = Self::new(user, &roles);
+        response.roles = Some(roles.into_iter().map(RoleResponse::from).collect());
+        response
+    }
+
+    /// Builds the responses for `users` from the roles assigned to each of
+    /// them, keyed by user id, embedding the roles as asked for by `include`.
+    pub fn for_users(
+        users: Vec<User>,
+        mut roles: HashMap<Uuid, Vec<Role>>,
+        include: Option<UserInclude>,
+    ) -> Vec<Self> {
+        users
+            .into_iter()
+            .map(|user| {
+                let roles = roles.remove(&user.id).unwrap_or_default();
+                match include {
+                    Some(UserInclude::Role) => Self::with_roles(user, roles),
+                    None => Self::new(user, &roles),
+                }
+            })
+            .collect()
+    }
+}
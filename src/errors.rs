@@ -0,0 +1,212 @@
+use crate::repositories::RepositoryError;
+use crate::services::auth_services::AuthError;
+use crate::services::role_services::RoleError;
+use crate::services::user_services::UserError;
+use crate::validation::ValidationErrors;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+/// The single error type returned by every handler and middleware.
+///
+/// Serializes as `{ "error": <message>, "code": <CODE>, "status": <status> }`,
+/// plus `field` for conflicts, `errors` for validation failures and
+/// `assigned_users` for roles that cannot be deleted while assigned.
+#[derive(Debug)]
+pub struct AppError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    pub field: Option<String>,
+    pub errors: Option<ValidationErrors>,
+    pub assigned_users: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+    code: &'static str,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<ValidationErrors>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    assigned_users: Option<usize>,
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        AppError {
+            status,
+            code,
+            message: message.into(),
+            field: None,
+            errors: None,
+            assigned_users: None,
+        }
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "UNAUTHORIZED", message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "FORBIDDEN", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "NOT_FOUND", message)
+    }
+
+    pub fn conflict(field: impl Into<String>, message: impl Into<String>) -> Self {
+        AppError {
+            field: Some(field.into()),
+            ..Self::new(StatusCode::CONFLICT, "CONFLICT", message)
+        }
+    }
+
+    /// A role cannot be deleted while `assigned_users` users still hold it.
+    pub fn role_in_use(message: impl Into<String>, assigned_users: usize) -> Self {
+        AppError {
+            assigned_users: Some(assigned_users),
+            ..Self::new(StatusCode::CONFLICT, "ROLE_IN_USE", message)
+        }
+    }
+
+    pub fn validation(errors: ValidationErrors) -> Self {
+        AppError {
+            errors: Some(errors),
+            ..Self::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "VALIDATION_FAILED",
+                "Validation failed",
+            )
+        }
+    }
+
+    pub fn precondition_failed(message: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::PRECONDITION_FAILED,
+            "PRECONDITION_FAILED",
+            message,
+        )
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", message)
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "SERVICE_UNAVAILABLE",
+            message,
+        )
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: self.message,
+            code: self.code,
+            status: self.status.as_u16(),
+            field: self.field,
+            errors: self.errors,
+            assigned_users: self.assigned_users,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<UserError> for AppError {
+    fn from(err: UserError) -> Self {
+        match err {
+            UserError::NotFound(msg) => AppError::not_found(msg),
+            UserError::Conflict { field, message } => AppError::conflict(field, message),
+            UserError::ValidationError(errors) => AppError::validation(errors),
+            UserError::Forbidden(msg) => AppError::forbidden(msg),
+            UserError::PreconditionFailed(msg) => AppError::precondition_failed(msg),
+            UserError::DatabaseError(msg) | UserError::HashError(msg) => AppError::internal(msg),
+            UserError::Unavailable(msg) => AppError::unavailable(msg),
+        }
+    }
+}
+
+impl From<RoleError> for AppError {
+    fn from(err: RoleError) -> Self {
+        match err {
+            RoleError::NotFound(msg) => AppError::not_found(msg),
+            RoleError::Conflict { field, message } => AppError::conflict(field, message),
+            RoleError::InUse {
+                message,
+                assigned_users,
+            } => AppError::role_in_use(message, assigned_users),
+            RoleError::ValidationError(errors) => AppError::validation(errors),
+            RoleError::PreconditionFailed(msg) => AppError::precondition_failed(msg),
+            RoleError::DatabaseError(msg) => AppError::internal(msg),
+            RoleError::Unavailable(msg) => AppError::unavailable(msg),
+        }
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::InvalidCredentials(msg) | AuthError::InvalidToken(msg) => {
+                AppError::unauthorized(msg)
+            }
+            AuthError::EmailNotVerified(msg) => {
+                AppError::new(StatusCode::FORBIDDEN, "EMAIL_NOT_VERIFIED", msg)
+            }
+            AuthError::ValidationError(errors) => AppError::validation(errors),
+            AuthError::DatabaseError(msg)
+            | AuthError::TokenError(msg)
+            | AuthError::HashError(msg) => AppError::internal(msg),
+            AuthError::Unavailable(msg) => AppError::unavailable(msg),
+        }
+    }
+}
+
+impl From<RepositoryError> for AppError {
+    fn from(err: RepositoryError) -> Self {
+        match err {
+            RepositoryError::NotFound => AppError::not_found("Resource not found"),
+            RepositoryError::UniqueViolation(field) => {
+                AppError::conflict(field.clone(), format!("{} already exists", field))
+            }
+            RepositoryError::Stale => {
+                AppError::precondition_failed("Resource has been modified since it was read")
+            }
+            RepositoryError::Unavailable(msg) => {
+                AppError::unavailable(format!("Database is unavailable: {}", msg))
+            }
+            RepositoryError::Database(msg) => {
+                AppError::internal(format!("Database error: {}", msg))
+            }
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::new(rejection.status(), "INVALID_BODY", rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::new(rejection.status(), "INVALID_QUERY", rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        AppError::new(rejection.status(), "INVALID_PATH", rejection.body_text())
+    }
+}
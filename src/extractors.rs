@@ -0,0 +1,121 @@
+//! Wrappers around axum's extractors that reject with [`AppError`] so that
+//! malformed bodies, query strings and path parameters get the same JSON error
+//! shape as everything else.
+
+use crate::errors::AppError;
+use crate::models::audit::{AuditContext, MAX_REQUEST_ID_LENGTH};
+use crate::models::auth::AuthUser;
+use crate::models::etag::IfMatch;
+use crate::models::organization::Tenant;
+use axum::async_trait;
+use axum::extract::{FromRequest, FromRequestParts};
+use axum::http::{HeaderName, StatusCode, header, request::Parts};
+use std::convert::Infallible;
+use uuid::Uuid;
+
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(AppError))]
+pub struct AppJson<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(AppError))]
+pub struct AppQuery<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(AppError))]
+pub struct AppPath<T>(pub T);
+
+/// Reads the `If-Match` header of a write request; its absence allows any
+/// version to be overwritten.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let values = parts
+            .headers
+            .get_all(header::IF_MATCH)
+            .iter()
+            .map(|value| value.to_str())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    "INVALID_HEADER",
+                    "If-Match must be visible ASCII",
+                )
+            })?;
+        if values.is_empty() {
+            return Ok(IfMatch::Any);
+        }
+        Ok(IfMatch::parse(&values.join(",")))
+    }
+}
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Identifies the caller and the request for the audit log. The actor is
+/// absent on routes that run without [`require_auth`].
+///
+/// [`require_auth`]: crate::middlewares::auth_middleware::require_auth
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuditContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(AuditContext {
+            actor_id: parts.extensions.get::<AuthUser>().map(|user| user.id),
+            request_id: parts
+                .headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(|id| id.chars().take(MAX_REQUEST_ID_LENGTH).collect()),
+        })
+    }
+}
+
+pub const ORGANIZATION_HEADER: HeaderName = HeaderName::from_static("x-organization-id");
+
+/// Resolves the organization a request acts in. Authenticated callers are
+/// bound to the organization in their token; a conflicting
+/// `X-Organization-Id` header is refused rather than ignored. Routes without
+/// [`require_auth`], such as login, take the organization from the header.
+///
+/// [`require_auth`]: crate::middlewares::auth_middleware::require_auth
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Tenant {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get(ORGANIZATION_HEADER)
+            .map(|value| {
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| Uuid::parse_str(value.trim()).ok())
+                    .ok_or_else(|| {
+                        AppError::new(
+                            StatusCode::BAD_REQUEST,
+                            "INVALID_HEADER",
+                            "X-Organization-Id must be a UUID",
+                        )
+                    })
+            })
+            .transpose()?;
+
+        match (parts.extensions.get::<AuthUser>(), header) {
+            (Some(user), Some(id)) if id != user.organization_id => Err(AppError::forbidden(
+                "Access to this organization is not allowed",
+            )),
+            (Some(user), _) => Ok(Tenant::new(user.organization_id)),
+            (None, Some(id)) => Ok(Tenant::new(id)),
+            (None, None) => Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "ORGANIZATION_REQUIRED",
+                "The X-Organization-Id header is required",
+            )),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::errors::AppError;
+use crate::extractors::AppQuery;
+use crate::models::organization::Tenant;
+use crate::models::query::{AuditListQuery, PageMeta, Pagination};
+use crate::routes::AppState;
+use crate::services::audit_services::AuditService;
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AuditHandler {
+    service: Arc<AuditService>,
+}
+
+impl AuditHandler {
+    pub fn new(service: AuditService) -> Self {
+        AuditHandler {
+            service: Arc::new(service),
+        }
+    }
+}
+
+pub async fn get_audit_events_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppQuery(filter): AppQuery<AuditListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let (events, total) = state
+        .audit_handler
+        .service
+        .get_events(tenant, &filter)
+        .await?;
+    let meta = PageMeta::new(Pagination::new(filter.page, filter.limit), total);
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "data": events, "meta": meta })),
+    ))
+}
@@ -0,0 +1,118 @@
+use crate::errors::AppError;
+use crate::extractors::AppJson;
+use crate::models::audit::AuditContext;
+use crate::models::auth::{
+    AuthUser, ForgotPasswordRequest, LoginRequest, RefreshRequest, ResetPasswordRequest,
+    VerifyEmailRequest,
+};
+use crate::models::organization::Tenant;
+use crate::routes::AppState;
+use crate::services::auth_services::{AuthError, AuthService};
+use axum::{Extension, Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AuthHandler {
+    service: Arc<AuthService>,
+}
+
+impl AuthHandler {
+    pub fn new(service: AuthService) -> Self {
+        AuthHandler {
+            service: Arc::new(service),
+        }
+    }
+
+    pub async fn authenticate(&self, token: &str) -> Result<AuthUser, AuthError> {
+        self.service.authenticate(token).await
+    }
+}
+
+pub async fn login_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppJson(payload): AppJson<LoginRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let token = state.auth_handler.service.login(tenant, payload).await?;
+    Ok((StatusCode::OK, Json(json!({ "data": token }))))
+}
+
+pub async fn refresh_handler(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<RefreshRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let token = state
+        .auth_handler
+        .service
+        .refresh(&payload.refresh_token)
+        .await?;
+    Ok((StatusCode::OK, Json(json!({ "data": token }))))
+}
+
+pub async fn logout_handler(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<RefreshRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .auth_handler
+        .service
+        .logout(&payload.refresh_token)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn logout_all_handler(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse, AppError> {
+    state.auth_handler.service.logout_all(auth_user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Answers at once and looks the account up in the background, so the
+/// response takes as long whether or not the email is registered.
+pub async fn forgot_password_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppJson(payload): AppJson<ForgotPasswordRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let service = state.auth_handler.service.clone();
+    tokio::spawn(async move {
+        if let Err(e) = service.forgot_password(tenant, &payload.email).await {
+            tracing::error!("Failed to issue password reset link: {:?}", e);
+        }
+    });
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": "If the email belongs to an account, a reset link has been sent"
+        })),
+    ))
+}
+
+pub async fn reset_password_handler(
+    State(state): State<AppState>,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<ResetPasswordRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .auth_handler
+        .service
+        .reset_password(payload, &ctx)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn verify_email_handler(
+    State(state): State<AppState>,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<VerifyEmailRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .auth_handler
+        .service
+        .verify_email(payload, &ctx)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,2 +1,4 @@
+pub mod audit_handler;
+pub mod auth_handler;
 pub mod role_handler;
 pub mod user_handler;
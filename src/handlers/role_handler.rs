@@ -1,10 +1,19 @@
-use crate::models::role::NewRole;
+use crate::dtos::role_dto::RoleResponse;
+use crate::errors::AppError;
+use crate::extractors::{AppJson, AppPath, AppQuery};
+use crate::models::audit::AuditContext;
+use crate::models::auth::AuthUser;
+use crate::models::etag::{IfMatch, etag};
+use crate::models::organization::Tenant;
+use crate::models::permission::{AssignPermission, NewPermission};
+use crate::models::query::{DeletedFilter, PageMeta, Pagination, RoleDeleteQuery, RoleListQuery};
+use crate::models::role::{NewRole, RolePatch};
 use crate::routes::AppState;
-use crate::services::role_services::{RoleError, RoleService};
+use crate::services::role_services::RoleService;
 use axum::{
-    Json,
-    extract::{Path, State},
-    http::StatusCode,
+    Extension, Json,
+    extract::State,
+    http::{StatusCode, header},
     response::IntoResponse,
 };
 use serde_json::json;
@@ -24,135 +33,234 @@ impl RoleHandler {
     }
 }
 
-pub async fn get_roles_handler(State(state): State<AppState>) -> impl IntoResponse {
-    match state.role_handler.service.get_roles() {
-        Ok(roles) => (StatusCode::OK, Json(json!({ "data": roles }))).into_response(),
-        Err(e) => match e {
-            RoleError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-            RoleError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(json!({
-                    "error": msg,
-                    "status": 404
-                })),
-            )
-                .into_response(),
-        },
+pub async fn get_roles_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppQuery(filter): AppQuery<RoleListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    if filter.include_deleted && !auth_user.is_admin() {
+        return Err(AppError::forbidden(
+            "Only administrators can include deleted roles",
+        ));
     }
+
+    let (roles, total) = state
+        .role_handler
+        .service
+        .get_roles(tenant, &filter)
+        .await?;
+    let roles: Vec<RoleResponse> = roles.into_iter().map(RoleResponse::from).collect();
+    let meta = PageMeta::new(Pagination::new(filter.page, filter.limit), total);
+    Ok((StatusCode::OK, Json(json!({ "data": roles, "meta": meta }))))
 }
 
 pub async fn create_role_handler(
     State(state): State<AppState>,
-    Json(payload): Json<NewRole>,
-) -> impl IntoResponse {
-    match state.role_handler.service.create_role(payload) {
-        Ok(role) => (StatusCode::CREATED, Json(json!({ "data": role }))).into_response(),
-        Err(e) => match e {
-            RoleError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-            _ => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": "Unexpected error occurred",
-                    "status": 500
-                })),
-            )
-                .into_response(),
-        },
-    }
+    tenant: Tenant,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<NewRole>,
+) -> Result<impl IntoResponse, AppError> {
+    let role = state
+        .role_handler
+        .service
+        .create_role(tenant, payload, &ctx)
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "data": RoleResponse::from(role) })),
+    ))
 }
 
 pub async fn get_role_handler(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> impl IntoResponse {
-    match state.role_handler.service.get_role(id) {
-        Ok(role) => (StatusCode::OK, Json(json!({ "data": role }))).into_response(),
-        Err(e) => match e {
-            RoleError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(json!({
-                    "error": msg,
-                    "status": 404
-                })),
-            )
-                .into_response(),
-            RoleError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-        },
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppPath(id): AppPath<Uuid>,
+    AppQuery(filter): AppQuery<DeletedFilter>,
+) -> Result<impl IntoResponse, AppError> {
+    if filter.include_deleted && !auth_user.is_admin() {
+        return Err(AppError::forbidden(
+            "Only administrators can include deleted roles",
+        ));
     }
+
+    let role = state
+        .role_handler
+        .service
+        .get_role(tenant, id, filter.include_deleted)
+        .await?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag(role.updated_at))],
+        Json(json!({ "data": RoleResponse::from(role) })),
+    ))
 }
 
 pub async fn update_role_handler(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-    Json(payload): Json<NewRole>,
-) -> impl IntoResponse {
-    match state.role_handler.service.update_role(id, payload) {
-        Ok(role) => (StatusCode::OK, Json(json!({ "data": role }))).into_response(),
-        Err(e) => match e {
-            RoleError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(json!({
-                    "error": msg,
-                    "status": 404
-                })),
-            )
-                .into_response(),
-            RoleError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-        },
-    }
+    tenant: Tenant,
+    AppPath(id): AppPath<Uuid>,
+    if_match: IfMatch,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<NewRole>,
+) -> Result<impl IntoResponse, AppError> {
+    let role = state
+        .role_handler
+        .service
+        .update_role(tenant, id, payload, &if_match, &ctx)
+        .await?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag(role.updated_at))],
+        Json(json!({ "data": RoleResponse::from(role) })),
+    ))
+}
+
+pub async fn patch_role_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppPath(id): AppPath<Uuid>,
+    if_match: IfMatch,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<RolePatch>,
+) -> Result<impl IntoResponse, AppError> {
+    let role = state
+        .role_handler
+        .service
+        .patch_role(tenant, id, payload, &if_match, &ctx)
+        .await?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag(role.updated_at))],
+        Json(json!({ "data": RoleResponse::from(role) })),
+    ))
+}
+
+pub async fn get_role_ancestors_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppPath(id): AppPath<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let roles = state
+        .role_handler
+        .service
+        .get_role_ancestors(tenant, id)
+        .await?;
+    let roles: Vec<RoleResponse> = roles.into_iter().map(RoleResponse::from).collect();
+    Ok((StatusCode::OK, Json(json!({ "data": roles }))))
 }
 
 pub async fn delete_role_handler(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> impl IntoResponse {
-    match state.role_handler.service.delete_role(id) {
-        Ok(role) => (StatusCode::OK, Json(json!({ "data": role }))).into_response(),
-        Err(e) => match e {
-            RoleError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(json!({
-                    "error": msg,
-                    "status": 404
-                })),
-            )
-                .into_response(),
-            RoleError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-        },
-    }
+    tenant: Tenant,
+    AppPath(id): AppPath<Uuid>,
+    AppQuery(options): AppQuery<RoleDeleteQuery>,
+    if_match: IfMatch,
+    ctx: AuditContext,
+) -> Result<impl IntoResponse, AppError> {
+    let role = state
+        .role_handler
+        .service
+        .delete_role(tenant, id, &options, &if_match, &ctx)
+        .await?;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "data": RoleResponse::from(role) })),
+    ))
+}
+
+pub async fn restore_role_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppPath(id): AppPath<Uuid>,
+    ctx: AuditContext,
+) -> Result<impl IntoResponse, AppError> {
+    let role = state
+        .role_handler
+        .service
+        .restore_role(tenant, id, &ctx)
+        .await?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag(role.updated_at))],
+        Json(json!({ "data": RoleResponse::from(role) })),
+    ))
+}
+
+pub async fn get_permissions_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+) -> Result<impl IntoResponse, AppError> {
+    let permissions = state.role_handler.service.get_permissions(tenant).await?;
+    Ok((StatusCode::OK, Json(json!({ "data": permissions }))))
+}
+
+pub async fn create_permission_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<NewPermission>,
+) -> Result<impl IntoResponse, AppError> {
+    let permission = state
+        .role_handler
+        .service
+        .create_permission(tenant, payload, &ctx)
+        .await?;
+    Ok((StatusCode::CREATED, Json(json!({ "data": permission }))))
+}
+
+pub async fn get_role_permissions_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppPath(id): AppPath<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let permissions = state
+        .role_handler
+        .service
+        .get_role_permissions(tenant, id)
+        .await?;
+    Ok((StatusCode::OK, Json(json!({ "data": permissions }))))
+}
+
+pub async fn assign_role_permission_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppPath(id): AppPath<Uuid>,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<AssignPermission>,
+) -> Result<impl IntoResponse, AppError> {
+    let permissions = state
+        .role_handler
+        .service
+        .assign_permission(tenant, id, payload.permission_id, &ctx)
+        .await?;
+    Ok((StatusCode::OK, Json(json!({ "data": permissions }))))
+}
+
+pub async fn revoke_role_permission_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppPath((id, permission_id)): AppPath<(Uuid, Uuid)>,
+    ctx: AuditContext,
+) -> Result<impl IntoResponse, AppError> {
+    let permissions = state
+        .role_handler
+        .service
+        .revoke_permission(tenant, id, permission_id, &ctx)
+        .await?;
+    Ok((StatusCode::OK, Json(json!({ "data": permissions }))))
+}
+
+pub async fn get_user_permissions_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    AppPath(id): AppPath<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let permissions = state
+        .role_handler
+        .service
+        .get_user_permissions(tenant, id)
+        .await?;
+    Ok((StatusCode::OK, Json(json!({ "data": permissions }))))
 }
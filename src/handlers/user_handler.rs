@@ -1,10 +1,19 @@
-use crate::models::user::NewUser;
+use crate::dtos::role_dto::RoleResponse;
+use crate::dtos::user_dto::UserResponse;
+use crate::errors::AppError;
+use crate::extractors::{AppJson, AppPath, AppQuery};
+use crate::models::audit::AuditContext;
+use crate::models::auth::AuthUser;
+use crate::models::etag::{IfMatch, etag};
+use crate::models::organization::Tenant;
+use crate::models::query::{PageMeta, Pagination, UserInclude, UserListQuery, UserQuery};
+use crate::models::user::{ChangePassword, NewUser, UpdateProfile, User, UserPatch};
 use crate::routes::AppState;
-use crate::services::user_services::{UserError, UserService};
+use crate::services::user_services::UserService;
 use axum::{
-    Json,
-    extract::{Path, State},
-    http::StatusCode,
+    Extension, Json,
+    extract::State,
+    http::{StatusCode, header},
     response::IntoResponse,
 };
 use serde_json::json;
@@ -24,157 +33,248 @@ impl UserHandler {
     }
 }
 
-pub async fn get_users_handler(State(state): State<AppState>) -> impl IntoResponse {
-    match state.user_handler.service.get_users() {
-        Ok(users) => (StatusCode::OK, Json(json!({ "data": users }))).into_response(),
-        Err(e) => match e {
-            UserError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-            UserError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(json!({
-                    "error": msg,
-                    "status": 404
-                })),
-            )
-                .into_response(),
-            UserError::ValidationError(_) => todo!(),
-            UserError::HashError(_) => todo!(),
-        },
+pub async fn get_users_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppQuery(filter): AppQuery<UserListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    if filter.include_deleted && !auth_user.is_admin() {
+        return Err(AppError::forbidden(
+            "Only administrators can include deleted users",
+        ));
     }
+
+    let (users, total) = state
+        .user_handler
+        .service
+        .get_users(tenant, &filter)
+        .await?;
+    let users = user_responses(&state, tenant, users, filter.include).await?;
+    let meta = PageMeta::new(Pagination::new(filter.page, filter.limit), total);
+    Ok((StatusCode::OK, Json(json!({ "data": users, "meta": meta }))))
 }
 
 pub async fn create_user_handler(
     State(state): State<AppState>,
-    Json(payload): Json<NewUser>,
-) -> impl IntoResponse {
-    match state.user_handler.service.create_user(payload) {
-        Ok(user) => (StatusCode::CREATED, Json(json!({ "data": user }))).into_response(),
-        Err(e) => match e {
-            UserError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-            UserError::ValidationError(msg) => (
-                StatusCode::BAD_REQUEST,
-                Json(json!({
-                    "error": msg,
-                    "status": 400
-                })),
-            )
-                .into_response(),
-            _ => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": "Unexpected error occurred",
-                    "status": 500
-                })),
-            )
-                .into_response(),
-        },
-    }
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<NewUser>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state
+        .user_handler
+        .service
+        .create_user(tenant, payload, &auth_user, &ctx)
+        .await?;
+    let mut users = user_responses(&state, tenant, vec![user], None).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "data": users.remove(0) })),
+    ))
 }
 
 pub async fn get_user_handler(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> impl IntoResponse {
-    match state.user_handler.service.get_user(id) {
-        Ok(user) => (StatusCode::OK, Json(json!({ "data": user }))).into_response(),
-        Err(e) => match e {
-            UserError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(json!({
-                    "error": msg,
-                    "status": 404
-                })),
-            )
-                .into_response(),
-            UserError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-            _ => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": "Unexpected error occurred",
-                    "status": 500
-                })),
-            )
-                .into_response(),
-        },
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppPath(id): AppPath<Uuid>,
+    AppQuery(filter): AppQuery<UserQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    if filter.include_deleted && !auth_user.is_admin() {
+        return Err(AppError::forbidden(
+            "Only administrators can include deleted users",
+        ));
     }
+
+    let user = state
+        .user_handler
+        .service
+        .get_user(tenant, id, filter.include_deleted)
+        .await?;
+    let tag = etag(user.updated_at);
+    let mut users = user_responses(&state, tenant, vec![user], filter.include).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, tag)],
+        Json(json!({ "data": users.remove(0) })),
+    ))
 }
 
 pub async fn update_user_handler(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-    Json(payload): Json<NewUser>,
-) -> impl IntoResponse {
-    match state.user_handler.service.update_user(id, payload) {
-        Ok(user) => (StatusCode::OK, Json(json!({ "data": user }))).into_response(),
-        Err(e) => match e {
-            UserError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-            UserError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(json!({
-                    "error": msg,
-                    "status": 404
-                })),
-            )
-                .into_response(),
-            UserError::ValidationError(_) => todo!(),
-            UserError::HashError(_) => todo!(),
-        },
-    }
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppPath(id): AppPath<Uuid>,
+    if_match: IfMatch,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<NewUser>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state
+        .user_handler
+        .service
+        .update_user(tenant, id, payload, &if_match, &auth_user, &ctx)
+        .await?;
+    let tag = etag(user.updated_at);
+    let mut users = user_responses(&state, tenant, vec![user], None).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, tag)],
+        Json(json!({ "data": users.remove(0) })),
+    ))
+}
+
+pub async fn patch_user_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppPath(id): AppPath<Uuid>,
+    if_match: IfMatch,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<UserPatch>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state
+        .user_handler
+        .service
+        .patch_user(tenant, id, payload, &if_match, &auth_user, &ctx)
+        .await?;
+    let tag = etag(user.updated_at);
+    let mut users = user_responses(&state, tenant, vec![user], None).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, tag)],
+        Json(json!({ "data": users.remove(0) })),
+    ))
 }
 
 pub async fn delete_user_handler(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> impl IntoResponse {
-    match state.user_handler.service.delete_user(id) {
-        Ok(_) => (StatusCode::OK, Json(json!({ "data": "User deleted" }))).into_response(),
-        Err(e) => match e {
-            UserError::DatabaseError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": msg,
-                    "status": 500
-                })),
-            )
-                .into_response(),
-            UserError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                Json(json!({
-                    "error": msg,
-                    "status": 404
-                })),
-            )
-                .into_response(),
-            UserError::ValidationError(_) => todo!(),
-            UserError::HashError(_) => todo!(),
-        },
-    }
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppPath(id): AppPath<Uuid>,
+    if_match: IfMatch,
+    ctx: AuditContext,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .user_handler
+        .service
+        .delete_user(tenant, id, &if_match, &auth_user, &ctx)
+        .await?;
+    Ok((StatusCode::OK, Json(json!({ "data": "User deleted" }))))
+}
+
+pub async fn restore_user_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppPath(id): AppPath<Uuid>,
+    ctx: AuditContext,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state
+        .user_handler
+        .service
+        .restore_user(tenant, id, &auth_user, &ctx)
+        .await?;
+    let tag = etag(user.updated_at);
+    let mut users = user_responses(&state, tenant, vec![user], None).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, tag)],
+        Json(json!({ "data": users.remove(0) })),
+    ))
+}
+
+pub async fn assign_user_role_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppPath((id, role_id)): AppPath<(Uuid, Uuid)>,
+    ctx: AuditContext,
+) -> Result<impl IntoResponse, AppError> {
+    let roles = state
+        .user_handler
+        .service
+        .assign_role(tenant, id, role_id, &auth_user, &ctx)
+        .await?;
+    let roles: Vec<RoleResponse> = roles.into_iter().map(RoleResponse::from).collect();
+    Ok((StatusCode::OK, Json(json!({ "data": roles }))))
+}
+
+pub async fn revoke_user_role_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    AppPath((id, role_id)): AppPath<(Uuid, Uuid)>,
+    ctx: AuditContext,
+) -> Result<impl IntoResponse, AppError> {
+    let roles = state
+        .user_handler
+        .service
+        .revoke_role(tenant, id, role_id, &auth_user, &ctx)
+        .await?;
+    let roles: Vec<RoleResponse> = roles.into_iter().map(RoleResponse::from).collect();
+    Ok((StatusCode::OK, Json(json!({ "data": roles }))))
+}
+
+pub async fn get_me_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state
+        .user_handler
+        .service
+        .get_user(tenant, auth_user.id, false)
+        .await?;
+    let mut users = user_responses(&state, tenant, vec![user], None).await?;
+    Ok((StatusCode::OK, Json(json!({ "data": users.remove(0) }))))
+}
+
+pub async fn update_me_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<UpdateProfile>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state
+        .user_handler
+        .service
+        .update_profile(tenant, auth_user.id, payload, &ctx)
+        .await?;
+    let mut users = user_responses(&state, tenant, vec![user], None).await?;
+    Ok((StatusCode::OK, Json(json!({ "data": users.remove(0) }))))
+}
+
+pub async fn change_password_handler(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(auth_user): Extension<AuthUser>,
+    ctx: AuditContext,
+    AppJson(payload): AppJson<ChangePassword>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .user_handler
+        .service
+        .change_password(tenant, auth_user.id, payload, &ctx)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Builds the responses for `users`, each with the roles assigned to them.
+/// The roles of all users are read in a single query after the users
+/// themselves, so a response costs two queries whatever its size, and
+/// embedding the roles as asked for by `include` costs none more.
+async fn user_responses(
+    state: &AppState,
+    tenant: Tenant,
+    users: Vec<User>,
+    include: Option<UserInclude>,
+) -> Result<Vec<UserResponse>, AppError> {
+    let roles = state
+        .user_handler
+        .service
+        .get_user_roles(tenant, &users)
+        .await?;
+    Ok(UserResponse::for_users(users, roles, include))
 }
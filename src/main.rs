@@ -1,36 +1,107 @@
-use crate::config::database::establish_connection;
+use crate::config::database::{Database, establish_connection};
+use crate::config::settings::Settings;
+use crate::handlers::audit_handler::AuditHandler;
+use crate::handlers::auth_handler::AuthHandler;
 use crate::handlers::role_handler::RoleHandler;
 use crate::handlers::user_handler::UserHandler;
+use crate::notifications::LogNotifier;
 use crate::repositories::user_repository::UserRepository;
 use crate::routes::create_router;
+use crate::services::audit_services::AuditService;
+use crate::services::auth_services::AuthService;
 use crate::services::role_services::RoleService;
 use crate::services::user_services::UserService;
 use axum::Router;
-use repositories::role_repository;
+use repositories::{
+    audit_repository, organization_repository, permission_repository, refresh_token_repository,
+    role_repository, unit_of_work,
+};
+use std::sync::Arc;
 use tokio::net::TcpListener;
 
 mod config;
+mod dtos;
+mod errors;
+mod extractors;
 mod handlers;
+mod middlewares;
 mod models;
+mod notifications;
 mod repositories;
 mod routes;
 mod schema;
 mod services;
+mod validation;
 
 #[tokio::main]
 async fn main() {
-    let pool = establish_connection();
+    let settings = Settings::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    tracing_subscriber::fmt()
+        .with_env_filter(settings.log.filter())
+        .init();
 
-    let user_repository = UserRepository::new(pool.clone());
-    let role_repository = role_repository::RoleRepository::new(pool.clone());
-    let role_service = RoleService::new(role_repository.clone());
-    let user_service = UserService::new(user_repository, role_repository.clone());
+    let pool = establish_connection(&settings.database).unwrap_or_else(|e| {
+        tracing::error!("Failed to create database pool: {}", e);
+        std::process::exit(1);
+    });
+    let db = Database::new(pool);
+
+    let user_repository = Arc::new(UserRepository::new(db.clone()));
+    let role_repository = Arc::new(role_repository::RoleRepository::new(db.clone()));
+    let permission_repository =
+        Arc::new(permission_repository::PermissionRepository::new(db.clone()));
+    let audit_repository = Arc::new(audit_repository::AuditRepository::new(db.clone()));
+    let unit_of_work = Arc::new(unit_of_work::PgUnitOfWork::new(db.clone()));
+    let notifier = Arc::new(LogNotifier);
+    let refresh_token_repository = Arc::new(refresh_token_repository::RefreshTokenRepository::new(
+        db.clone(),
+    ));
+    let role_service = RoleService::new(
+        role_repository.clone(),
+        permission_repository,
+        user_repository.clone(),
+        unit_of_work.clone(),
+    );
+    let auth_service = AuthService::new(
+        user_repository.clone(),
+        role_repository.clone(),
+        Arc::new(organization_repository::OrganizationRepository::new(
+            db.clone(),
+        )),
+        refresh_token_repository,
+        unit_of_work.clone(),
+        notifier.clone(),
+        settings.auth.clone(),
+    );
+    let user_service = UserService::new(
+        user_repository,
+        role_repository,
+        unit_of_work,
+        notifier,
+        settings.auth.clone(),
+    );
+    let audit_service = AuditService::new(audit_repository);
+    let audit_handler = AuditHandler::new(audit_service);
+    let auth_handler = AuthHandler::new(auth_service);
     let user_handler = UserHandler::new(user_service);
     let role_handler = RoleHandler::new(role_service);
 
-    let app: Router = create_router(user_handler, role_handler);
+    let app: Router = create_router(
+        audit_handler,
+        auth_handler,
+        user_handler,
+        role_handler,
+        settings.cors.layer(),
+    );
 
-    let listener = TcpListener::bind("127.0.0.1:3000").await.unwrap();
-    println!("🚀 Server running on http://127.0.0.1:3000");
+    let address = settings.server.address();
+    let listener = TcpListener::bind(&address).await.unwrap_or_else(|e| {
+        tracing::error!("Failed to bind {}: {}", address, e);
+        std::process::exit(1);
+    });
+    tracing::info!("🚀 Server running on http://{}", address);
     axum::serve(listener, app).await.unwrap();
 }
@@ -0,0 +1,50 @@
+use crate::errors::AppError;
+use crate::models::auth::AuthUser;
+use crate::routes::AppState;
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+
+/// Rejects requests without a valid `Authorization: Bearer <token>` header and
+/// makes the resolved [`AuthUser`] available as a request extension for
+/// downstream middleware and handlers.
+pub async fn require_auth(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| AppError::unauthorized("Missing bearer token"))?;
+
+    let user = state.auth_handler.authenticate(token).await?;
+    req.extensions_mut().insert(user);
+    Ok(next.run(req).await)
+}
+
+/// Allows the request through only when the authenticated caller has at least
+/// one of the `allowed` role codes. Must run after [`require_auth`].
+pub async fn require_roles(
+    allowed: &'static [&'static str],
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let user = req
+        .extensions()
+        .get::<AuthUser>()
+        .ok_or_else(|| AppError::unauthorized("Missing bearer token"))?;
+
+    if !allowed.iter().any(|code| user.has_role(code)) {
+        return Err(AppError::forbidden(
+            "You do not have permission to access this resource",
+        ));
+    }
+
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,200 @@
+use crate::models::permission::Permission;
+use crate::models::role::Role;
+use crate::models::user::User;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+
+/// Fields whose values never appear in an audit trail, only the fact that
+/// they changed.
+const REDACTED_FIELDS: &[&str] = &["password"];
+const REDACTED: &str = "[redacted]";
+
+/// Length of the `audit_events.request_id` column. Request ids come from
+/// clients too, so longer ones are cut to fit.
+pub const MAX_REQUEST_ID_LENGTH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Create => "create",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+            AuditAction::Restore => "restore",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::audit_events)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    /// The changed fields as `{ "field": { "before": .., "after": .. } }`.
+    pub changes: Value,
+    pub request_id: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub organization_id: Uuid,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::audit_events)]
+pub struct NewAuditEvent {
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub changes: Value,
+    pub request_id: Option<String>,
+}
+
+/// An entity whose mutations are recorded in the audit log.
+pub trait Audited {
+    const ENTITY_TYPE: &'static str;
+
+    fn audit_id(&self) -> Uuid;
+
+    /// The fields compared between the states before and after a change.
+    fn snapshot(&self) -> Value;
+}
+
+impl Audited for User {
+    const ENTITY_TYPE: &'static str = "user";
+
+    fn audit_id(&self) -> Uuid {
+        self.id
+    }
+
+    fn snapshot(&self) -> Value {
+        json!({
+            "name": self.name,
+            "email": self.email,
+            "password": self.password,
+            "email_verified_at": self.email_verified_at,
+            "deleted_at": self.deleted_at,
+        })
+    }
+}
+
+impl Audited for Role {
+    const ENTITY_TYPE: &'static str = "role";
+
+    fn audit_id(&self) -> Uuid {
+        self.id
+    }
+
+    fn snapshot(&self) -> Value {
+        json!({
+            "name": self.name,
+            "code": self.code,
+            "description": self.description,
+            "parent_role_id": self.parent_role_id,
+            "deleted_at": self.deleted_at,
+        })
+    }
+}
+
+impl Audited for Permission {
+    const ENTITY_TYPE: &'static str = "permission";
+
+    fn audit_id(&self) -> Uuid {
+        self.id
+    }
+
+    fn snapshot(&self) -> Value {
+        json!({
+            "name": self.name,
+            "code": self.code,
+            "description": self.description,
+        })
+    }
+}
+
+/// Who makes a change and in which request, taken from the authenticated
+/// caller and the `x-request-id` header.
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub actor_id: Option<Uuid>,
+    pub request_id: Option<String>,
+}
+
+impl AuditContext {
+    /// Records `action` on `after`, diffed against its state `before` (none
+    /// for a creation).
+    pub fn event<T: Audited>(
+        &self,
+        action: AuditAction,
+        before: Option<&T>,
+        after: &T,
+    ) -> NewAuditEvent {
+        let before = before.map_or(Value::Null, Audited::snapshot);
+        self.event_with(
+            action,
+            T::ENTITY_TYPE,
+            after.audit_id(),
+            &before,
+            &after.snapshot(),
+        )
+    }
+
+    /// Like [`Self::event`] for changes that are not captured by an entity's
+    /// snapshot, such as the permissions assigned to a role.
+    pub fn event_with(
+        &self,
+        action: AuditAction,
+        entity_type: &str,
+        entity_id: Uuid,
+        before: &Value,
+        after: &Value,
+    ) -> NewAuditEvent {
+        NewAuditEvent {
+            actor_id: self.actor_id,
+            action: action.as_str().to_string(),
+            entity_type: entity_type.to_string(),
+            entity_id,
+            changes: diff(before, after),
+            request_id: self.request_id.clone(),
+        }
+    }
+}
+
+/// Compares two JSON objects field by field, keeping only the fields whose
+/// values differ. `Null` counts as an object without fields.
+fn diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for field in before.keys().chain(after.keys()) {
+        let old = before.get(field).unwrap_or(&Value::Null);
+        let new = after.get(field).unwrap_or(&Value::Null);
+        if old == new || changes.contains_key(field) {
+            continue;
+        }
+        let (old, new) = if REDACTED_FIELDS.contains(&field.as_str()) {
+            let redact = |value: &Value| match value {
+                Value::Null => Value::Null,
+                _ => Value::from(REDACTED),
+            };
+            (redact(old), redact(new))
+        } else {
+            (old.clone(), new.clone())
+        };
+        changes.insert(field.clone(), json!({ "before": old, "after": new }));
+    }
+    Value::Object(changes)
+}
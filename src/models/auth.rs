@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub refresh_token: String,
+    pub refresh_expires_in: i64,
+}
+
+/// Body of `POST /auth/refresh` and `POST /auth/logout`.
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// Body of `POST /auth/password/forgot`.
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+/// Body of `POST /auth/password/reset`.
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+/// Body of `POST /auth/verify-email`.
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+/// Claims carried by a signed access token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,
+    /// The organization the user belongs to.
+    pub org: Uuid,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// The caller resolved from a verified access token, with their current roles.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    /// The codes of the user's roles and of every role those inherit from.
+    pub role_codes: Vec<String>,
+}
+
+impl AuthUser {
+    pub fn has_role(&self, code: &str) -> bool {
+        self.role_codes.iter().any(|role_code| role_code == code)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.has_role(crate::models::role::ROLE_ADMIN)
+    }
+}
@@ -0,0 +1,25 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// A stored email verification token, looked up by the SHA-256 hash of the
+/// token. It confirms `email` only, so it stops working once the user changes
+/// their address again.
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::email_verification_tokens)]
+pub struct EmailVerificationToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub email: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::email_verification_tokens)]
+pub struct NewEmailVerificationToken {
+    pub user_id: Uuid,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
@@ -0,0 +1,41 @@
+use chrono::NaiveDateTime;
+
+/// Strong entity tag of a user or role, derived from its `updated_at`.
+pub fn etag(updated_at: NaiveDateTime) -> String {
+    format!("\"{:x}\"", updated_at.and_utc().timestamp_micros())
+}
+
+/// The `If-Match` precondition of a write request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum IfMatch {
+    /// No header, or `*`: any current version may be overwritten.
+    #[default]
+    Any,
+    /// The listed entity tags, one of which must be current.
+    Tags(Vec<String>),
+}
+
+impl IfMatch {
+    /// Parses a header value such as `"1a2b", "3c4d"` or `*`.
+    pub fn parse(value: &str) -> Self {
+        if value.trim() == "*" {
+            return IfMatch::Any;
+        }
+        IfMatch::Tags(
+            value
+                .split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        )
+    }
+
+    /// Whether a row last updated at `updated_at` satisfies the precondition.
+    /// Weak tags never match, as `If-Match` requires strong comparison.
+    pub fn matches(&self, updated_at: NaiveDateTime) -> bool {
+        match self {
+            IfMatch::Any => true,
+            IfMatch::Tags(tags) => tags.contains(&etag(updated_at)),
+        }
+    }
+}
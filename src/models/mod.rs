@@ -1,2 +1,11 @@
+pub mod audit;
+pub mod auth;
+pub mod email_verification;
+pub mod etag;
+pub mod organization;
+pub mod password_reset;
+pub mod permission;
+pub mod query;
+pub mod refresh_token;
 pub mod role;
 pub mod user;
@@ -0,0 +1,39 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// A customer organization. Users and roles always belong to exactly one.
+#[derive(Debug, Clone, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::organizations)]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+    pub slug: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// The organization a request acts in, resolved from the caller's access
+/// token or, before login, from the `X-Organization-Id` header.
+///
+/// Every user, role and audit store method takes one and only ever reads or
+/// writes rows of that organization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tenant(Uuid);
+
+impl Tenant {
+    pub fn new(organization_id: Uuid) -> Self {
+        Tenant(organization_id)
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.0
+    }
+}
+
+impl From<&Organization> for Tenant {
+    fn from(organization: &Organization) -> Self {
+        Tenant(organization.id)
+    }
+}
@@ -0,0 +1,21 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// A stored password reset token, looked up by the SHA-256 hash of the token.
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::password_reset_tokens)]
+pub struct PasswordResetToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::password_reset_tokens)]
+pub struct NewPasswordResetToken {
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
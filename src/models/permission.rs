@@ -0,0 +1,47 @@
+use crate::validation::{MAX_VARCHAR_LENGTH, Validate, ValidationErrors, check_code, check_text};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::permissions)]
+pub struct Permission {
+    pub id: Uuid,
+    pub name: String,
+    pub code: String,
+    pub description: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub organization_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = crate::schema::permissions)]
+pub struct NewPermission {
+    pub name: String,
+    pub code: String,
+    pub description: String,
+}
+
+impl Validate for NewPermission {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        check_text(&mut errors, "name", &self.name, MAX_VARCHAR_LENGTH);
+        check_code(&mut errors, "code", &self.code);
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::role_permissions)]
+pub struct NewRolePermission {
+    pub role_id: Uuid,
+    pub permission_id: Uuid,
+    pub organization_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignPermission {
+    pub permission_id: Uuid,
+}
@@ -0,0 +1,168 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 20;
+pub const MAX_PAGE_LIMIT: i64 = 100;
+/// The highest page whose offset still fits in an `i64` at any limit.
+pub const MAX_PAGE: i64 = i64::MAX / MAX_PAGE_LIMIT;
+
+/// `?include_deleted=true` lets administrators see soft-deleted rows.
+#[derive(Debug, Default, Deserialize)]
+pub struct DeletedFilter {
+    #[serde(default)]
+    pub include_deleted: bool,
+}
+
+/// Query parameters accepted by `DELETE /roles/:id`. A role still assigned
+/// to live users is only deleted when one of them says what happens to
+/// those users; they cannot be combined.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RoleDeleteQuery {
+    /// Moves the users over to this role before the deletion.
+    pub reassign_to: Option<Uuid>,
+    /// Deletes the role regardless. The users' assignments are soft deleted
+    /// with it, and restoring the role hands it back to them.
+    pub force: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSortField {
+    Name,
+    Email,
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoleSortField {
+    Name,
+    Code,
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Related data that `?include=` embeds in user responses.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserInclude {
+    /// The full role objects assigned to each user, as `roles`.
+    #[serde(alias = "roles")]
+    Role,
+}
+
+/// Query parameters accepted by `GET /users/:id`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct UserQuery {
+    pub include_deleted: bool,
+    pub include: Option<UserInclude>,
+}
+
+/// Query parameters accepted by `GET /users`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct UserListQuery {
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+    pub sort_by: UserSortField,
+    pub sort_dir: SortDirection,
+    pub role_id: Option<Uuid>,
+    /// Case-insensitive substring match on the email address.
+    pub email: Option<String>,
+    /// Case-insensitive substring match on the name.
+    pub name: Option<String>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    pub include_deleted: bool,
+    pub include: Option<UserInclude>,
+}
+
+/// Query parameters accepted by `GET /roles`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct RoleListQuery {
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+    pub sort_by: RoleSortField,
+    pub sort_dir: SortDirection,
+    /// Case-insensitive substring match on the role code.
+    pub code: Option<String>,
+    /// Case-insensitive substring match on the role name.
+    pub name: Option<String>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    pub include_deleted: bool,
+}
+
+/// Query parameters accepted by `GET /audit`. Events are listed newest
+/// first.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct AuditListQuery {
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+    /// `user`, `role` or `permission`.
+    pub entity_type: Option<String>,
+    pub entity_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    /// `create`, `update`, `delete` or `restore`.
+    pub action: Option<String>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+}
+
+/// A page/limit pair clamped to sane bounds.
+#[derive(Debug, Clone, Copy)]
+pub struct Pagination {
+    pub page: i64,
+    pub limit: i64,
+}
+
+impl Pagination {
+    pub fn new(page: Option<i64>, limit: Option<i64>) -> Self {
+        Pagination {
+            page: page.unwrap_or(1).clamp(1, MAX_PAGE),
+            limit: limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT),
+        }
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.limit
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageMeta {
+    pub page: i64,
+    pub limit: i64,
+    pub total: i64,
+    pub total_pages: i64,
+    pub next_page: Option<i64>,
+}
+
+impl PageMeta {
+    pub fn new(pagination: Pagination, total: i64) -> Self {
+        let total_pages = (total + pagination.limit - 1) / pagination.limit;
+        PageMeta {
+            page: pagination.page,
+            limit: pagination.limit,
+            total,
+            total_pages,
+            next_page: (pagination.page < total_pages).then_some(pagination.page + 1),
+        }
+    }
+}
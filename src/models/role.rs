@@ -1,10 +1,15 @@
+use crate::validation::{MAX_VARCHAR_LENGTH, Validate, ValidationErrors, check_code, check_text};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, AsChangeset)]
-#[diesel(table_name = crate::schema::roles)]
+pub const ROLE_ADMIN: &str = "ADMIN";
+pub const ROLE_USER_MANAGER: &str = "USER_MANAGER";
+
+/// A role row. Updates write every column, so `None` clears the column.
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::roles, treat_none_as_null = true)]
 pub struct Role {
     pub id: Uuid,
     pub name: String,
@@ -13,6 +18,9 @@ pub struct Role {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub deleted_at: Option<NaiveDateTime>,
+    pub organization_id: Uuid,
+    /// The role this one inherits every permission from.
+    pub parent_role_id: Option<Uuid>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -21,4 +29,58 @@ pub struct NewRole {
     pub name: String,
     pub code: String,
     pub description: String,
+    #[serde(default)]
+    pub parent_role_id: Option<Uuid>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::user_roles)]
+pub struct NewUserRole {
+    pub user_id: Uuid,
+    pub role_id: Uuid,
+    pub organization_id: Uuid,
+}
+
+impl Validate for NewRole {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        check_text(&mut errors, "name", &self.name, MAX_VARCHAR_LENGTH);
+        check_code(&mut errors, "code", &self.code);
+        errors.into_result()
+    }
+}
+
+/// Body of `PATCH /roles/:id`. Omitted fields are left unchanged.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RolePatch {
+    pub name: Option<String>,
+    pub code: Option<String>,
+    pub description: Option<String>,
+    /// `null` detaches the role from its parent.
+    #[serde(default, deserialize_with = "present")]
+    pub parent_role_id: Option<Option<Uuid>>,
+}
+
+/// Marks a field as present even when its value is `null`, so that `null`
+/// can be told apart from an omitted field.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+impl Validate for RolePatch {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if let Some(name) = &self.name {
+            check_text(&mut errors, "name", name, MAX_VARCHAR_LENGTH);
+        }
+        if let Some(code) = &self.code {
+            check_code(&mut errors, "code", code);
+        }
+        errors.into_result()
+    }
 }
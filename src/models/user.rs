@@ -1,18 +1,24 @@
+use crate::validation::{
+    MAX_VARCHAR_LENGTH, Validate, ValidationErrors, check_email, check_password, check_text,
+};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, AsChangeset)]
-#[diesel(table_name = crate::schema::users)]
+/// A user row. Updates write every column, so `None` clears the column.
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::users, treat_none_as_null = true)]
 pub struct User {
     pub id: Uuid,
     pub name: String,
     pub email: String,
     pub password: String,
-    pub role_id: Uuid,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
     pub deleted_at: Option<chrono::NaiveDateTime>,
+    /// When the current `email` was confirmed; cleared whenever it changes.
+    pub email_verified_at: Option<chrono::NaiveDateTime>,
+    pub organization_id: Uuid,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -21,5 +27,60 @@ pub struct NewUser {
     pub name: String,
     pub email: String,
     pub password: String,
-    pub role_id: Uuid,
+    /// The roles to assign; they are stored apart from the user row.
+    #[diesel(skip_insertion)]
+    pub role_ids: Vec<Uuid>,
+}
+
+impl Validate for NewUser {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        check_text(&mut errors, "name", &self.name, MAX_VARCHAR_LENGTH);
+        check_email(&mut errors, "email", &self.email);
+        check_password(&mut errors, "password", &self.password);
+        errors.into_result()
+    }
+}
+
+/// Body of `PATCH /users/:id`. Omitted fields are left unchanged.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserPatch {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub password: Option<String>,
+    /// Replaces the assigned roles as a whole.
+    pub role_ids: Option<Vec<Uuid>>,
+}
+
+impl Validate for UserPatch {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if let Some(name) = &self.name {
+            check_text(&mut errors, "name", name, MAX_VARCHAR_LENGTH);
+        }
+        if let Some(email) = &self.email {
+            check_email(&mut errors, "email", email);
+        }
+        if let Some(password) = &self.password {
+            check_password(&mut errors, "password", password);
+        }
+        errors.into_result()
+    }
+}
+
+/// Body of `PATCH /me`. Users may change their name and email but not their
+/// role, so unknown fields are rejected rather than ignored.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateProfile {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Body of `POST /me/password`.
+#[derive(Debug, Deserialize)]
+pub struct ChangePassword {
+    pub current_password: String,
+    pub new_password: String,
 }
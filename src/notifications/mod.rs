@@ -0,0 +1,65 @@
+use async_trait::async_trait;
+use std::fmt;
+
+/// A message to deliver to a user, such as a password reset link.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct NotifyError(pub String);
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Delivers notifications to users. Implement this to send email, SMS or
+/// anything else; [`LogNotifier`] is enough for local development.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, notification: Notification) -> Result<(), NotifyError>;
+}
+
+/// Writes notifications to the application log instead of delivering them.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn send(&self, notification: Notification) -> Result<(), NotifyError> {
+        tracing::info!(
+            to = %notification.to,
+            subject = %notification.subject,
+            "Notification:\n{}",
+            notification.body
+        );
+        Ok(())
+    }
+}
+
+/// Keeps sent notifications in memory so tests can inspect them.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingNotifier {
+    sent: std::sync::Arc<std::sync::Mutex<Vec<Notification>>>,
+}
+
+#[cfg(test)]
+impl RecordingNotifier {
+    pub fn sent(&self) -> Vec<Notification> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Notifier for RecordingNotifier {
+    async fn send(&self, notification: Notification) -> Result<(), NotifyError> {
+        self.sent.lock().unwrap().push(notification);
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use crate::config::database::Database;
+use crate::models::audit::{AuditEvent, NewAuditEvent};
+use crate::models::organization::Tenant;
+use crate::models::query::{AuditListQuery, Pagination};
+use crate::repositories::{AuditStore, RepositoryError};
+use crate::schema::audit_events;
+use crate::schema::audit_events::dsl::*;
+use async_trait::async_trait;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+
+#[derive(Clone)]
+pub struct AuditRepository {
+    pub db: Database,
+}
+
+impl AuditRepository {
+    pub fn new(db: Database) -> Self {
+        AuditRepository { db }
+    }
+
+    fn filtered(tenant: Tenant, filter: &AuditListQuery) -> audit_events::BoxedQuery<'static, Pg> {
+        let mut query = audit_events
+            .filter(organization_id.eq(tenant.id()))
+            .into_boxed();
+        if let Some(entity) = &filter.entity_type {
+            query = query.filter(entity_type.eq(entity.clone()));
+        }
+        if let Some(entity) = filter.entity_id {
+            query = query.filter(entity_id.eq(entity));
+        }
+        if let Some(actor) = filter.actor_id {
+            query = query.filter(actor_id.eq(actor));
+        }
+        if let Some(kind) = &filter.action {
+            query = query.filter(action.eq(kind.clone()));
+        }
+        if let Some(from) = filter.created_from {
+            query = query.filter(created_at.ge(from.naive_utc()));
+        }
+        if let Some(to) = filter.created_to {
+            query = query.filter(created_at.le(to.naive_utc()));
+        }
+        query
+    }
+}
+
+#[async_trait]
+impl AuditStore for AuditRepository {
+    async fn record(
+        &self,
+        tenant: Tenant,
+        event: NewAuditEvent,
+    ) -> Result<AuditEvent, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(audit_events)
+                    .values((&event, organization_id.eq(tenant.id())))
+                    .returning(AuditEvent::as_returning())
+                    .get_result(conn)
+            })
+            .await
+    }
+
+    /// Returns one page of events matching `filter`, newest first, together
+    /// with the total number of matching rows.
+    async fn find_all(
+        &self,
+        tenant: Tenant,
+        filter: &AuditListQuery,
+    ) -> Result<(Vec<AuditEvent>, i64), RepositoryError> {
+        let filter = filter.clone();
+        self.db
+            .run(move |conn| {
+                let pagination = Pagination::new(filter.page, filter.limit);
+
+                let total = Self::filtered(tenant, &filter)
+                    .count()
+                    .get_result::<i64>(conn)?;
+                let data = Self::filtered(tenant, &filter)
+                    .order((created_at.desc(), id.desc()))
+                    .limit(pagination.limit)
+                    .offset(pagination.offset())
+                    .select(AuditEvent::as_select())
+                    .load(conn)?;
+
+                Ok((data, total))
+            })
+            .await
+    }
+}
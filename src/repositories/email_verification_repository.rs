@@ -0,0 +1,76 @@
+use crate::config::database::Database;
+use crate::models::email_verification::{EmailVerificationToken, NewEmailVerificationToken};
+use crate::repositories::{EmailVerificationStore, RepositoryError};
+use crate::schema::email_verification_tokens::dsl::*;
+use async_trait::async_trait;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct EmailVerificationRepository {
+    pub db: Database,
+}
+
+impl EmailVerificationRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl EmailVerificationStore for EmailVerificationRepository {
+    async fn create(
+        &self,
+        token: NewEmailVerificationToken,
+    ) -> Result<EmailVerificationToken, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(email_verification_tokens)
+                    .values(&token)
+                    .returning(EmailVerificationToken::as_returning())
+                    .get_result(conn)
+            })
+            .await
+    }
+
+    async fn find_by_hash(&self, hash: &str) -> Result<EmailVerificationToken, RepositoryError> {
+        let hash = hash.to_string();
+        self.db
+            .run(move |conn| {
+                email_verification_tokens
+                    .filter(token_hash.eq(hash))
+                    .select(EmailVerificationToken::as_select())
+                    .first(conn)
+            })
+            .await
+    }
+
+    async fn consume(&self, token_id: Uuid) -> Result<bool, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    email_verification_tokens
+                        .find(token_id)
+                        .filter(used_at.is_null()),
+                )
+                .set(used_at.eq(Some(chrono::Utc::now().naive_utc())))
+                .execute(conn)
+                .map(|updated| updated > 0)
+            })
+            .await
+    }
+
+    async fn invalidate_user(&self, owner: Uuid) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    email_verification_tokens
+                        .filter(user_id.eq(owner))
+                        .filter(used_at.is_null()),
+                )
+                .set(used_at.eq(Some(chrono::Utc::now().naive_utc())))
+                .execute(conn)
+            })
+            .await
+    }
+}
@@ -0,0 +1,1152 @@
+//! In-memory implementations of the store traits, used to exercise the
+//! service layer without PostgreSQL. They mirror the behaviour of the Diesel
+//! repositories: organization scoping, soft deletes, case-insensitive unique
+//! emails and role codes, filtering, sorting and pagination.
+
+use crate::models::audit::{AuditEvent, NewAuditEvent};
+use crate::models::email_verification::{EmailVerificationToken, NewEmailVerificationToken};
+use crate::models::organization::{Organization, Tenant};
+use crate::models::password_reset::{NewPasswordResetToken, PasswordResetToken};
+use crate::models::permission::{NewPermission, Permission};
+use crate::models::query::{
+    AuditListQuery, Pagination, RoleListQuery, RoleSortField, SortDirection, UserListQuery,
+    UserSortField,
+};
+use crate::models::refresh_token::{NewRefreshToken, RefreshToken};
+use crate::models::role::{NewRole, Role};
+use crate::models::user::{NewUser, User};
+use crate::repositories::{
+    AuditStore, EmailVerificationStore, LockMode, OrganizationStore, PasswordResetStore,
+    PermissionStore, RefreshTokenStore, RepositoryError, RoleStore, Transaction, UnitOfWork,
+    UserStore,
+};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use std::cmp::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard};
+use uuid::Uuid;
+
+#[derive(Clone, Default)]
+struct MemoryData {
+    organizations: Vec<Organization>,
+    users: Vec<User>,
+    roles: Vec<Role>,
+    permissions: Vec<Permission>,
+    role_permissions: Vec<(Uuid, Uuid)>,
+    /// Role assignments as `(user_id, role_id)`.
+    user_roles: Vec<(Uuid, Uuid)>,
+    /// Soft-deleted role assignments, kept apart from the live ones.
+    suspended_user_roles: Vec<(Uuid, Uuid)>,
+    /// Refresh tokens keyed by their hash.
+    refresh_tokens: Vec<(String, RefreshToken)>,
+    /// Password reset tokens keyed by their hash.
+    password_reset_tokens: Vec<(String, PasswordResetToken)>,
+    /// Email verification tokens keyed by their hash.
+    email_verification_tokens: Vec<(String, EmailVerificationToken)>,
+    audit_events: Vec<AuditEvent>,
+}
+
+/// A shared in-memory database. Clones share the same data, so one store can
+/// be handed to several services as each of the store traits.
+#[derive(Clone, Default)]
+pub struct InMemoryStore {
+    data: Arc<Mutex<MemoryData>>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn data(&self) -> MutexGuard<'_, MemoryData> {
+        self.data.lock().expect("in-memory store poisoned")
+    }
+
+    /// Adds an organization, which only exists as seed data in PostgreSQL.
+    pub fn create_organization(&self, slug: &str) -> Tenant {
+        let organization = Organization {
+            id: Uuid::new_v4(),
+            name: slug.to_string(),
+            slug: slug.to_string(),
+            created_at: now(),
+            updated_at: now(),
+        };
+        let tenant = Tenant::from(&organization);
+        self.data().organizations.push(organization);
+        tenant
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+fn contains(haystack: &str, needle: &Option<String>) -> bool {
+    needle
+        .as_ref()
+        .is_none_or(|needle| haystack.to_lowercase().contains(&needle.to_lowercase()))
+}
+
+fn paginate<T>(mut rows: Vec<T>, page: Option<i64>, limit: Option<i64>) -> (Vec<T>, i64) {
+    let pagination = Pagination::new(page, limit);
+    let total = rows.len() as i64;
+    let rows = rows
+        .drain(..)
+        .skip(pagination.offset() as usize)
+        .take(pagination.limit as usize)
+        .collect();
+    (rows, total)
+}
+
+fn directed(ordering: Ordering, direction: SortDirection) -> Ordering {
+    match direction {
+        SortDirection::Asc => ordering,
+        SortDirection::Desc => ordering.reverse(),
+    }
+}
+
+fn email_taken(data: &MemoryData, tenant: Tenant, email: &str, except: Option<Uuid>) -> bool {
+    data.users.iter().any(|u| {
+        u.organization_id == tenant.id()
+            && u.deleted_at.is_none()
+            && Some(u.id) != except
+            && u.email.to_lowercase() == email.to_lowercase()
+    })
+}
+
+fn code_taken(data: &MemoryData, tenant: Tenant, code: &str, except: Option<Uuid>) -> bool {
+    data.roles.iter().any(|r| {
+        r.organization_id == tenant.id()
+            && r.deleted_at.is_none()
+            && Some(r.id) != except
+            && r.code.to_lowercase() == code.to_lowercase()
+    })
+}
+
+#[async_trait]
+impl UserStore for InMemoryStore {
+    async fn get_users(
+        &self,
+        tenant: Tenant,
+        filter: &UserListQuery,
+    ) -> Result<(Vec<User>, i64), RepositoryError> {
+        let data = self.data();
+        let mut rows: Vec<User> = data
+            .users
+            .iter()
+            .filter(|u| u.organization_id == tenant.id())
+            .filter(|u| filter.include_deleted || u.deleted_at.is_none())
+            .filter(|u| {
+                filter
+                    .role_id
+                    .is_none_or(|role_id| data.user_roles.contains(&(u.id, role_id)))
+            })
+            .filter(|u| contains(&u.email, &filter.email) && contains(&u.name, &filter.name))
+            .filter(|u| {
+                filter
+                    .created_from
+                    .is_none_or(|from| u.created_at >= from.naive_utc())
+            })
+            .filter(|u| {
+                filter
+                    .created_to
+                    .is_none_or(|to| u.created_at <= to.naive_utc())
+            })
+            .cloned()
+            .collect();
+        rows.sort_by(|a, b| {
+            let ordering = match filter.sort_by {
+                UserSortField::Name => a.name.cmp(&b.name),
+                UserSortField::Email => a.email.cmp(&b.email),
+                UserSortField::CreatedAt => a.created_at.cmp(&b.created_at),
+                UserSortField::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+            };
+            directed(ordering, filter.sort_dir).then(a.id.cmp(&b.id))
+        });
+        Ok(paginate(rows, filter.page, filter.limit))
+    }
+
+    async fn create_user(
+        &self,
+        tenant: Tenant,
+        new_user: NewUser,
+    ) -> Result<User, RepositoryError> {
+        let mut data = self.data();
+        if email_taken(&data, tenant, &new_user.email, None) {
+            return Err(RepositoryError::UniqueViolation("email".to_string()));
+        }
+        let user = User {
+            id: Uuid::new_v4(),
+            name: new_user.name,
+            email: new_user.email,
+            password: new_user.password,
+            created_at: now(),
+            updated_at: now(),
+            deleted_at: None,
+            email_verified_at: None,
+            organization_id: tenant.id(),
+        };
+        data.users.push(user.clone());
+        Ok(user)
+    }
+
+    async fn get_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<User, RepositoryError> {
+        self.data()
+            .users
+            .iter()
+            .find(|u| {
+                u.organization_id == tenant.id()
+                    && u.id == user_id
+                    && (include_deleted || u.deleted_at.is_none())
+            })
+            .cloned()
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn get_user_by_email(
+        &self,
+        tenant: Tenant,
+        user_email: &str,
+    ) -> Result<User, RepositoryError> {
+        self.data()
+            .users
+            .iter()
+            .find(|u| {
+                u.organization_id == tenant.id()
+                    && u.deleted_at.is_none()
+                    && u.email.to_lowercase() == user_email.to_lowercase()
+            })
+            .cloned()
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    /// Every write holds the store's mutex, so there is nothing to lock.
+    async fn lock_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        _mode: LockMode,
+    ) -> Result<User, RepositoryError> {
+        self.get_user(tenant, user_id, false).await
+    }
+
+    async fn update_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        user_upd: User,
+        expected: NaiveDateTime,
+    ) -> Result<User, RepositoryError> {
+        let mut data = self.data();
+        if email_taken(&data, tenant, &user_upd.email, Some(user_id)) {
+            return Err(RepositoryError::UniqueViolation("email".to_string()));
+        }
+        let user = data
+            .users
+            .iter_mut()
+            .find(|u| u.organization_id == tenant.id() && u.id == user_id && u.deleted_at.is_none())
+            .ok_or(RepositoryError::NotFound)?;
+        if user.updated_at != expected {
+            return Err(RepositoryError::Stale);
+        }
+        user.name = user_upd.name;
+        user.email = user_upd.email;
+        user.password = user_upd.password;
+        user.updated_at = user_upd.updated_at;
+        user.email_verified_at = user_upd.email_verified_at;
+        Ok(user.clone())
+    }
+
+    async fn delete_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        expected: NaiveDateTime,
+    ) -> Result<User, RepositoryError> {
+        let mut data = self.data();
+        let user = data
+            .users
+            .iter_mut()
+            .find(|u| u.organization_id == tenant.id() && u.id == user_id && u.deleted_at.is_none())
+            .ok_or(RepositoryError::NotFound)?;
+        if user.updated_at != expected {
+            return Err(RepositoryError::Stale);
+        }
+        user.deleted_at = Some(now());
+        user.updated_at = now();
+        Ok(user.clone())
+    }
+
+    async fn restore_user(&self, tenant: Tenant, user_id: Uuid) -> Result<User, RepositoryError> {
+        let mut data = self.data();
+        let email = data
+            .users
+            .iter()
+            .find(|u| u.organization_id == tenant.id() && u.id == user_id && u.deleted_at.is_some())
+            .map(|u| u.email.clone())
+            .ok_or(RepositoryError::NotFound)?;
+        if email_taken(&data, tenant, &email, Some(user_id)) {
+            return Err(RepositoryError::UniqueViolation("email".to_string()));
+        }
+        let user = data
+            .users
+            .iter_mut()
+            .find(|u| u.organization_id == tenant.id() && u.id == user_id)
+            .ok_or(RepositoryError::NotFound)?;
+        user.deleted_at = None;
+        user.updated_at = now();
+        Ok(user.clone())
+    }
+}
+
+#[async_trait]
+impl RoleStore for InMemoryStore {
+    async fn find_all(
+        &self,
+        tenant: Tenant,
+        filter: &RoleListQuery,
+    ) -> Result<(Vec<Role>, i64), RepositoryError> {
+        let data = self.data();
+        let mut rows: Vec<Role> = data
+            .roles
+            .iter()
+            .filter(|r| r.organization_id == tenant.id())
+            .filter(|r| filter.include_deleted || r.deleted_at.is_none())
+            .filter(|r| contains(&r.code, &filter.code) && contains(&r.name, &filter.name))
+            .filter(|r| {
+                filter
+                    .created_from
+                    .is_none_or(|from| r.created_at >= from.naive_utc())
+            })
+            .filter(|r| {
+                filter
+                    .created_to
+                    .is_none_or(|to| r.created_at <= to.naive_utc())
+            })
+            .cloned()
+            .collect();
+        rows.sort_by(|a, b| {
+            let ordering = match filter.sort_by {
+                RoleSortField::Name => a.name.cmp(&b.name),
+                RoleSortField::Code => a.code.cmp(&b.code),
+                RoleSortField::CreatedAt => a.created_at.cmp(&b.created_at),
+                RoleSortField::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+            };
+            directed(ordering, filter.sort_dir).then(a.id.cmp(&b.id))
+        });
+        Ok(paginate(rows, filter.page, filter.limit))
+    }
+
+    async fn find_by_id(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<Role, RepositoryError> {
+        self.data()
+            .roles
+            .iter()
+            .find(|r| {
+                r.organization_id == tenant.id()
+                    && r.id == role_id
+                    && (include_deleted || r.deleted_at.is_none())
+            })
+            .cloned()
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn lock(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        _mode: LockMode,
+    ) -> Result<Role, RepositoryError> {
+        RoleStore::find_by_id(self, tenant, role_id, false).await
+    }
+
+    async fn create(&self, tenant: Tenant, role: NewRole) -> Result<Role, RepositoryError> {
+        let mut data = self.data();
+        if code_taken(&data, tenant, &role.code, None) {
+            return Err(RepositoryError::UniqueViolation("code".to_string()));
+        }
+        check_parent(&data, tenant, None, role.parent_role_id)?;
+        let role = Role {
+            id: Uuid::new_v4(),
+            name: role.name,
+            code: role.code,
+            description: role.description,
+            created_at: now(),
+            updated_at: now(),
+            deleted_at: None,
+            organization_id: tenant.id(),
+            parent_role_id: role.parent_role_id,
+        };
+        data.roles.push(role.clone());
+        Ok(role)
+    }
+
+    async fn update(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        role_upd: Role,
+        expected: NaiveDateTime,
+    ) -> Result<Role, RepositoryError> {
+        let mut data = self.data();
+        if code_taken(&data, tenant, &role_upd.code, Some(role_id)) {
+            return Err(RepositoryError::UniqueViolation("code".to_string()));
+        }
+        check_parent(&data, tenant, Some(role_id), role_upd.parent_role_id)?;
+        let role = data
+            .roles
+            .iter_mut()
+            .find(|r| r.organization_id == tenant.id() && r.id == role_id && r.deleted_at.is_none())
+            .ok_or(RepositoryError::NotFound)?;
+        if role.updated_at != expected {
+            return Err(RepositoryError::Stale);
+        }
+        role.name = role_upd.name;
+        role.code = role_upd.code;
+        role.description = role_upd.description;
+        role.parent_role_id = role_upd.parent_role_id;
+        role.updated_at = role_upd.updated_at;
+        Ok(role.clone())
+    }
+
+    async fn delete(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        expected: NaiveDateTime,
+    ) -> Result<Role, RepositoryError> {
+        let mut data = self.data();
+        let role = data
+            .roles
+            .iter_mut()
+            .find(|r| r.organization_id == tenant.id() && r.id == role_id && r.deleted_at.is_none())
+            .ok_or(RepositoryError::NotFound)?;
+        if role.updated_at != expected {
+            return Err(RepositoryError::Stale);
+        }
+        role.deleted_at = Some(now());
+        role.updated_at = now();
+        Ok(role.clone())
+    }
+
+    async fn restore(&self, tenant: Tenant, role_id: Uuid) -> Result<Role, RepositoryError> {
+        let mut data = self.data();
+        let code = data
+            .roles
+            .iter()
+            .find(|r| r.organization_id == tenant.id() && r.id == role_id && r.deleted_at.is_some())
+            .map(|r| r.code.clone())
+            .ok_or(RepositoryError::NotFound)?;
+        if code_taken(&data, tenant, &code, Some(role_id)) {
+            return Err(RepositoryError::UniqueViolation("code".to_string()));
+        }
+        let role = data
+            .roles
+            .iter_mut()
+            .find(|r| r.organization_id == tenant.id() && r.id == role_id)
+            .ok_or(RepositoryError::NotFound)?;
+        role.deleted_at = None;
+        role.updated_at = now();
+        Ok(role.clone())
+    }
+
+    async fn find_ancestors(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<Vec<Role>, RepositoryError> {
+        let data = self.data();
+        let role = data
+            .roles
+            .iter()
+            .find(|r| r.organization_id == tenant.id() && r.id == role_id)
+            .ok_or(RepositoryError::NotFound)?;
+        let mut ancestors: Vec<Role> = Vec::new();
+        let mut parent = role.parent_role_id;
+        while let Some(parent_id) = parent {
+            if parent_id == role_id || ancestors.iter().any(|r| r.id == parent_id) {
+                break;
+            }
+            let Some(role) = data.roles.iter().find(|r| {
+                r.organization_id == tenant.id()
+                    && r.id == parent_id
+                    && (include_deleted || r.deleted_at.is_none())
+            }) else {
+                break;
+            };
+            parent = role.parent_role_id;
+            ancestors.push(role.clone());
+        }
+        Ok(ancestors)
+    }
+
+    async fn find_by_users(
+        &self,
+        tenant: Tenant,
+        user_ids: &[Uuid],
+    ) -> Result<Vec<(Uuid, Role)>, RepositoryError> {
+        let data = self.data();
+        let mut rows: Vec<(Uuid, Role)> = data
+            .user_roles
+            .iter()
+            .filter(|(user_id, _)| user_ids.contains(user_id))
+            .filter_map(|(user_id, role_id)| {
+                data.roles
+                    .iter()
+                    .find(|r| {
+                        r.organization_id == tenant.id()
+                            && r.id == *role_id
+                            && r.deleted_at.is_none()
+                    })
+                    .map(|r| (*user_id, r.clone()))
+            })
+            .collect();
+        rows.sort_by(|(a, ra), (b, rb)| a.cmp(b).then(ra.code.cmp(&rb.code)));
+        Ok(rows)
+    }
+
+    async fn find_user_ids(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Uuid>, RepositoryError> {
+        let data = self.data();
+        let mut user_ids: Vec<Uuid> = data
+            .users
+            .iter()
+            .filter(|u| u.organization_id == tenant.id() && u.deleted_at.is_none())
+            .filter(|u| data.user_roles.contains(&(u.id, role_id)))
+            .map(|u| u.id)
+            .collect();
+        user_ids.sort();
+        Ok(user_ids)
+    }
+
+    async fn assign(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        let mut data = self.data();
+        let user_exists = data
+            .users
+            .iter()
+            .any(|u| u.organization_id == tenant.id() && u.id == user_id);
+        let role_exists = data
+            .roles
+            .iter()
+            .any(|r| r.organization_id == tenant.id() && r.id == role_id);
+        if !user_exists || !role_exists {
+            return Err(RepositoryError::Database(
+                "violates foreign key constraint".to_string(),
+            ));
+        }
+        if data.user_roles.contains(&(user_id, role_id)) {
+            return Ok(0);
+        }
+        data.user_roles.push((user_id, role_id));
+        Ok(1)
+    }
+
+    async fn unassign(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        let mut data = self.data();
+        let in_tenant = data
+            .users
+            .iter()
+            .any(|u| u.organization_id == tenant.id() && u.id == user_id);
+        let before = data.user_roles.len();
+        data.user_roles
+            .retain(|pair| !in_tenant || *pair != (user_id, role_id));
+        Ok(before - data.user_roles.len())
+    }
+
+    async fn suspend(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        let removed = RoleStore::unassign(self, tenant, user_id, role_id).await?;
+        if removed > 0 {
+            self.data().suspended_user_roles.push((user_id, role_id));
+        }
+        Ok(removed)
+    }
+
+    async fn resume(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        let mut data = self.data();
+        let in_tenant = data
+            .users
+            .iter()
+            .any(|u| u.organization_id == tenant.id() && u.id == user_id);
+        let before = data.suspended_user_roles.len();
+        data.suspended_user_roles
+            .retain(|pair| !in_tenant || *pair != (user_id, role_id));
+        let resumed = before - data.suspended_user_roles.len();
+        if resumed > 0 {
+            data.user_roles.push((user_id, role_id));
+        }
+        Ok(resumed)
+    }
+
+    async fn find_suspended_user_ids(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Uuid>, RepositoryError> {
+        let data = self.data();
+        let mut user_ids: Vec<Uuid> = data
+            .users
+            .iter()
+            .filter(|u| u.organization_id == tenant.id() && u.deleted_at.is_none())
+            .filter(|u| data.suspended_user_roles.contains(&(u.id, role_id)))
+            .map(|u| u.id)
+            .collect();
+        user_ids.sort();
+        Ok(user_ids)
+    }
+}
+
+#[async_trait]
+impl OrganizationStore for InMemoryStore {
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Organization, RepositoryError> {
+        let data = self.data();
+        let user = data
+            .users
+            .iter()
+            .find(|u| u.id == user_id)
+            .ok_or(RepositoryError::NotFound)?;
+        data.organizations
+            .iter()
+            .find(|o| o.id == user.organization_id)
+            .cloned()
+            .ok_or(RepositoryError::NotFound)
+    }
+}
+
+#[async_trait]
+impl PermissionStore for InMemoryStore {
+    async fn find_all(&self, tenant: Tenant) -> Result<Vec<Permission>, RepositoryError> {
+        let mut rows: Vec<Permission> = self
+            .data()
+            .permissions
+            .iter()
+            .filter(|p| p.organization_id == tenant.id())
+            .cloned()
+            .collect();
+        rows.sort_by(|a, b| a.code.cmp(&b.code));
+        Ok(rows)
+    }
+
+    async fn find_by_id(
+        &self,
+        tenant: Tenant,
+        permission_id: Uuid,
+    ) -> Result<Permission, RepositoryError> {
+        self.data()
+            .permissions
+            .iter()
+            .find(|p| p.organization_id == tenant.id() && p.id == permission_id)
+            .cloned()
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn create(
+        &self,
+        tenant: Tenant,
+        permission: NewPermission,
+    ) -> Result<Permission, RepositoryError> {
+        let mut data = self.data();
+        if data.permissions.iter().any(|p| {
+            p.organization_id == tenant.id()
+                && p.code.to_lowercase() == permission.code.to_lowercase()
+        }) {
+            return Err(RepositoryError::UniqueViolation("code".to_string()));
+        }
+        let permission = Permission {
+            id: Uuid::new_v4(),
+            name: permission.name,
+            code: permission.code,
+            description: permission.description,
+            created_at: now(),
+            updated_at: now(),
+            organization_id: tenant.id(),
+        };
+        data.permissions.push(permission.clone());
+        Ok(permission)
+    }
+
+    async fn find_by_role(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Permission>, RepositoryError> {
+        let data = self.data();
+        let mut rows: Vec<Permission> = data
+            .permissions
+            .iter()
+            .filter(|p| {
+                p.organization_id == tenant.id() && data.role_permissions.contains(&(role_id, p.id))
+            })
+            .cloned()
+            .collect();
+        rows.sort_by(|a, b| a.code.cmp(&b.code));
+        Ok(rows)
+    }
+
+    async fn assign(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        permission_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        let mut data = self.data();
+        let role_exists = data
+            .roles
+            .iter()
+            .any(|r| r.organization_id == tenant.id() && r.id == role_id);
+        let permission_exists = data
+            .permissions
+            .iter()
+            .any(|p| p.organization_id == tenant.id() && p.id == permission_id);
+        if !role_exists || !permission_exists {
+            return Err(RepositoryError::Database(
+                "violates foreign key constraint".to_string(),
+            ));
+        }
+        if data.role_permissions.contains(&(role_id, permission_id)) {
+            return Ok(0);
+        }
+        data.role_permissions.push((role_id, permission_id));
+        Ok(1)
+    }
+
+    async fn unassign(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        permission_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        let mut data = self.data();
+        let owned = data
+            .permissions
+            .iter()
+            .any(|p| p.organization_id == tenant.id() && p.id == permission_id);
+        if !owned {
+            return Ok(0);
+        }
+        let before = data.role_permissions.len();
+        data.role_permissions
+            .retain(|pair| *pair != (role_id, permission_id));
+        Ok(before - data.role_permissions.len())
+    }
+
+    async fn find_codes_by_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+    ) -> Result<Vec<String>, RepositoryError> {
+        let data = self.data();
+        let Some(user) = data.users.iter().find(|u| {
+            u.organization_id == tenant.id() && u.id == user_id && u.deleted_at.is_none()
+        }) else {
+            return Ok(Vec::new());
+        };
+        let mut role_ids: Vec<Uuid> = data
+            .user_roles
+            .iter()
+            .filter(|(user_id, _)| *user_id == user.id)
+            .map(|(_, role_id)| *role_id)
+            .filter(|role_id| {
+                data.roles
+                    .iter()
+                    .any(|r| r.id == *role_id && r.deleted_at.is_none())
+            })
+            .collect();
+        let mut i = 0;
+        while i < role_ids.len() {
+            let parent = data
+                .roles
+                .iter()
+                .find(|r| r.id == role_ids[i])
+                .and_then(|r| r.parent_role_id)
+                .and_then(|parent_id| {
+                    data.roles
+                        .iter()
+                        .find(|r| r.id == parent_id && r.deleted_at.is_none())
+                });
+            if let Some(parent) = parent
+                && !role_ids.contains(&parent.id)
+            {
+                role_ids.push(parent.id);
+            }
+            i += 1;
+        }
+        let mut codes: Vec<String> = data
+            .permissions
+            .iter()
+            .filter(|p| p.organization_id == tenant.id())
+            .filter(|p| {
+                role_ids
+                    .iter()
+                    .any(|role_id| data.role_permissions.contains(&(*role_id, p.id)))
+            })
+            .map(|p| p.code.clone())
+            .collect();
+        codes.sort();
+        codes.dedup();
+        Ok(codes)
+    }
+}
+
+/// Mirrors the foreign key and check constraint on `roles.parent_role_id`.
+fn check_parent(
+    data: &MemoryData,
+    tenant: Tenant,
+    role_id: Option<Uuid>,
+    parent_role_id: Option<Uuid>,
+) -> Result<(), RepositoryError> {
+    let Some(parent_id) = parent_role_id else {
+        return Ok(());
+    };
+    let exists = data
+        .roles
+        .iter()
+        .any(|r| r.organization_id == tenant.id() && r.id == parent_id);
+    if !exists || role_id == Some(parent_id) {
+        return Err(RepositoryError::Database(
+            "violates parent role constraint".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn insert_refresh_token(data: &mut MemoryData, token: NewRefreshToken) -> RefreshToken {
+    let token_hash = token.token_hash;
+    let token = RefreshToken {
+        id: Uuid::new_v4(),
+        user_id: token.user_id,
+        family_id: token.family_id,
+        expires_at: token.expires_at,
+        used_at: None,
+        revoked_at: None,
+    };
+    data.refresh_tokens.push((token_hash, token.clone()));
+    token
+}
+
+fn revoke_refresh_tokens(data: &mut MemoryData, matches: impl Fn(&RefreshToken) -> bool) -> usize {
+    let mut revoked = 0;
+    for (_, token) in data
+        .refresh_tokens
+        .iter_mut()
+        .filter(|(_, t)| t.revoked_at.is_none() && matches(t))
+    {
+        token.revoked_at = Some(now());
+        revoked += 1;
+    }
+    revoked
+}
+
+#[async_trait]
+impl RefreshTokenStore for InMemoryStore {
+    async fn create(&self, token: NewRefreshToken) -> Result<RefreshToken, RepositoryError> {
+        Ok(insert_refresh_token(&mut self.data(), token))
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> Result<RefreshToken, RepositoryError> {
+        self.data()
+            .refresh_tokens
+            .iter()
+            .find(|(hash, _)| hash == token_hash)
+            .map(|(_, t)| t.clone())
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn rotate(
+        &self,
+        token_id: Uuid,
+        successor: NewRefreshToken,
+    ) -> Result<Option<RefreshToken>, RepositoryError> {
+        let mut data = self.data();
+        let Some((_, token)) = data
+            .refresh_tokens
+            .iter_mut()
+            .find(|(_, t)| t.id == token_id && t.used_at.is_none() && t.revoked_at.is_none())
+        else {
+            return Ok(None);
+        };
+        token.used_at = Some(now());
+        Ok(Some(insert_refresh_token(&mut data, successor)))
+    }
+
+    async fn revoke_family(&self, family_id: Uuid) -> Result<usize, RepositoryError> {
+        Ok(revoke_refresh_tokens(&mut self.data(), |t| {
+            t.family_id == family_id
+        }))
+    }
+
+    async fn revoke_user(&self, user_id: Uuid) -> Result<usize, RepositoryError> {
+        Ok(revoke_refresh_tokens(&mut self.data(), |t| {
+            t.user_id == user_id
+        }))
+    }
+}
+
+#[async_trait]
+impl PasswordResetStore for InMemoryStore {
+    async fn create(
+        &self,
+        token: NewPasswordResetToken,
+    ) -> Result<PasswordResetToken, RepositoryError> {
+        let stored = PasswordResetToken {
+            id: Uuid::new_v4(),
+            user_id: token.user_id,
+            expires_at: token.expires_at,
+            used_at: None,
+        };
+        self.data()
+            .password_reset_tokens
+            .push((token.token_hash, stored.clone()));
+        Ok(stored)
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> Result<PasswordResetToken, RepositoryError> {
+        self.data()
+            .password_reset_tokens
+            .iter()
+            .find(|(hash, _)| hash == token_hash)
+            .map(|(_, t)| t.clone())
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn consume(&self, token_id: Uuid) -> Result<bool, RepositoryError> {
+        let mut data = self.data();
+        match data
+            .password_reset_tokens
+            .iter_mut()
+            .find(|(_, t)| t.id == token_id && t.used_at.is_none())
+        {
+            Some((_, token)) => {
+                token.used_at = Some(now());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn invalidate_user(&self, user_id: Uuid) -> Result<usize, RepositoryError> {
+        let mut data = self.data();
+        let mut invalidated = 0;
+        for (_, token) in data
+            .password_reset_tokens
+            .iter_mut()
+            .filter(|(_, t)| t.user_id == user_id && t.used_at.is_none())
+        {
+            token.used_at = Some(now());
+            invalidated += 1;
+        }
+        Ok(invalidated)
+    }
+}
+
+#[async_trait]
+impl EmailVerificationStore for InMemoryStore {
+    async fn create(
+        &self,
+        token: NewEmailVerificationToken,
+    ) -> Result<EmailVerificationToken, RepositoryError> {
+        let stored = EmailVerificationToken {
+            id: Uuid::new_v4(),
+            user_id: token.user_id,
+            email: token.email,
+            expires_at: token.expires_at,
+            used_at: None,
+        };
+        self.data()
+            .email_verification_tokens
+            .push((token.token_hash, stored.clone()));
+        Ok(stored)
+    }
+
+    async fn find_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<EmailVerificationToken, RepositoryError> {
+        self.data()
+            .email_verification_tokens
+            .iter()
+            .find(|(hash, _)| hash == token_hash)
+            .map(|(_, t)| t.clone())
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn consume(&self, token_id: Uuid) -> Result<bool, RepositoryError> {
+        let mut data = self.data();
+        match data
+            .email_verification_tokens
+            .iter_mut()
+            .find(|(_, t)| t.id == token_id && t.used_at.is_none())
+        {
+            Some((_, token)) => {
+                token.used_at = Some(now());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn invalidate_user(&self, user_id: Uuid) -> Result<usize, RepositoryError> {
+        let mut data = self.data();
+        let mut invalidated = 0;
+        for (_, token) in data
+            .email_verification_tokens
+            .iter_mut()
+            .filter(|(_, t)| t.user_id == user_id && t.used_at.is_none())
+        {
+            token.used_at = Some(now());
+            invalidated += 1;
+        }
+        Ok(invalidated)
+    }
+}
+
+#[async_trait]
+impl AuditStore for InMemoryStore {
+    async fn record(
+        &self,
+        tenant: Tenant,
+        event: NewAuditEvent,
+    ) -> Result<AuditEvent, RepositoryError> {
+        let event = AuditEvent {
+            id: Uuid::new_v4(),
+            actor_id: event.actor_id,
+            action: event.action,
+            entity_type: event.entity_type,
+            entity_id: event.entity_id,
+            changes: event.changes,
+            request_id: event.request_id,
+            created_at: now(),
+            organization_id: tenant.id(),
+        };
+        self.data().audit_events.push(event.clone());
+        Ok(event)
+    }
+
+    async fn find_all(
+        &self,
+        tenant: Tenant,
+        filter: &AuditListQuery,
+    ) -> Result<(Vec<AuditEvent>, i64), RepositoryError> {
+        let data = self.data();
+        let rows: Vec<AuditEvent> = data
+            .audit_events
+            .iter()
+            .rev()
+            .filter(|e| e.organization_id == tenant.id())
+            .filter(|e| {
+                filter
+                    .entity_type
+                    .as_ref()
+                    .is_none_or(|t| e.entity_type == *t)
+            })
+            .filter(|e| filter.entity_id.is_none_or(|id| e.entity_id == id))
+            .filter(|e| filter.actor_id.is_none_or(|id| e.actor_id == Some(id)))
+            .filter(|e| filter.action.as_ref().is_none_or(|a| e.action == *a))
+            .filter(|e| {
+                filter
+                    .created_from
+                    .is_none_or(|from| e.created_at >= from.naive_utc())
+            })
+            .filter(|e| {
+                filter
+                    .created_to
+                    .is_none_or(|to| e.created_at <= to.naive_utc())
+            })
+            .cloned()
+            .collect();
+        Ok(paginate(rows, filter.page, filter.limit))
+    }
+}
+
+/// A transaction over the in-memory store: writes go straight to the shared
+/// data, and rolling back restores the snapshot taken when it began.
+pub struct MemoryTransaction {
+    store: InMemoryStore,
+    snapshot: MemoryData,
+}
+
+#[async_trait]
+impl UnitOfWork for InMemoryStore {
+    async fn begin(&self) -> Result<Arc<dyn Transaction>, RepositoryError> {
+        Ok(Arc::new(MemoryTransaction {
+            store: self.clone(),
+            snapshot: self.data().clone(),
+        }))
+    }
+}
+
+#[async_trait]
+impl Transaction for MemoryTransaction {
+    fn organizations(&self) -> &dyn OrganizationStore {
+        &self.store
+    }
+
+    fn users(&self) -> &dyn UserStore {
+        &self.store
+    }
+
+    fn roles(&self) -> &dyn RoleStore {
+        &self.store
+    }
+
+    fn permissions(&self) -> &dyn PermissionStore {
+        &self.store
+    }
+
+    fn refresh_tokens(&self) -> &dyn RefreshTokenStore {
+        &self.store
+    }
+
+    fn password_resets(&self) -> &dyn PasswordResetStore {
+        &self.store
+    }
+
+    fn email_verifications(&self) -> &dyn EmailVerificationStore {
+        &self.store
+    }
+
+    fn audit(&self) -> &dyn AuditStore {
+        &self.store
+    }
+
+    async fn commit(&self) -> Result<(), RepositoryError> {
+        Ok(())
+    }
+
+    async fn rollback(&self) -> Result<(), RepositoryError> {
+        *self.store.data() = self.snapshot.clone();
+        Ok(())
+    }
+}
@@ -1,2 +1,393 @@
+pub mod audit_repository;
+pub mod email_verification_repository;
+#[cfg(test)]
+pub mod memory;
+pub mod organization_repository;
+pub mod password_reset_repository;
+pub mod permission_repository;
+pub mod refresh_token_repository;
 pub mod role_repository;
+pub mod unit_of_work;
 pub mod user_repository;
+
+use crate::models::audit::{AuditEvent, NewAuditEvent};
+use crate::models::email_verification::{EmailVerificationToken, NewEmailVerificationToken};
+use crate::models::organization::{Organization, Tenant};
+use crate::models::password_reset::{NewPasswordResetToken, PasswordResetToken};
+use crate::models::permission::{NewPermission, Permission};
+use crate::models::query::{AuditListQuery, RoleListQuery, UserListQuery};
+use crate::models::refresh_token::{NewRefreshToken, RefreshToken};
+use crate::models::role::{NewRole, Role};
+use crate::models::user::{NewUser, User};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use std::future::Future;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Storage failures as seen by the service layer.
+#[derive(Debug)]
+pub enum RepositoryError {
+    NotFound,
+    /// A unique constraint was violated; carries the field it guards.
+    UniqueViolation(String),
+    /// The row was changed since the version the write was based on.
+    Stale,
+    /// No database connection could be obtained.
+    Unavailable(String),
+    Database(String),
+}
+
+impl From<DieselError> for RepositoryError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => RepositoryError::NotFound,
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                RepositoryError::UniqueViolation(unique_violation_field(info.constraint_name()))
+            }
+            _ => RepositoryError::Database(err.to_string()),
+        }
+    }
+}
+
+/// The row lock taken by a locking read. It is held until the surrounding
+/// transaction ends, and released at once outside of one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockMode {
+    /// Lets others read and share-lock the row but not change it, for rows a
+    /// write depends on.
+    Share,
+    /// Excludes every other lock, for rows about to be changed.
+    Update,
+}
+
+/// Persistence of users, implemented by [`user_repository::UserRepository`]
+/// for PostgreSQL. Every method only sees the users of `tenant`, and reads
+/// exclude soft-deleted rows unless asked otherwise.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn get_users(
+        &self,
+        tenant: Tenant,
+        filter: &UserListQuery,
+    ) -> Result<(Vec<User>, i64), RepositoryError>;
+    async fn create_user(&self, tenant: Tenant, new_user: NewUser)
+    -> Result<User, RepositoryError>;
+    async fn get_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<User, RepositoryError>;
+    async fn get_user_by_email(
+        &self,
+        tenant: Tenant,
+        user_email: &str,
+    ) -> Result<User, RepositoryError>;
+    /// Fetches a live user and locks its row.
+    async fn lock_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        mode: LockMode,
+    ) -> Result<User, RepositoryError>;
+    /// Writes `user_upd` only while the stored `updated_at` still equals
+    /// `expected`, failing with [`RepositoryError::Stale`] otherwise.
+    async fn update_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        user_upd: User,
+        expected: NaiveDateTime,
+    ) -> Result<User, RepositoryError>;
+    /// Soft deletes the user, guarded by `expected` like [`Self::update_user`].
+    async fn delete_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        expected: NaiveDateTime,
+    ) -> Result<User, RepositoryError>;
+    async fn restore_user(&self, tenant: Tenant, user_id: Uuid) -> Result<User, RepositoryError>;
+}
+
+/// Persistence of roles, implemented by [`role_repository::RoleRepository`].
+/// Every method only sees the roles of `tenant`.
+#[async_trait]
+pub trait RoleStore: Send + Sync {
+    async fn find_all(
+        &self,
+        tenant: Tenant,
+        filter: &RoleListQuery,
+    ) -> Result<(Vec<Role>, i64), RepositoryError>;
+    async fn find_by_id(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<Role, RepositoryError>;
+    /// Fetches a live role and locks its row.
+    async fn lock(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        mode: LockMode,
+    ) -> Result<Role, RepositoryError>;
+    async fn create(&self, tenant: Tenant, role: NewRole) -> Result<Role, RepositoryError>;
+    /// Writes `role` only while the stored `updated_at` still equals
+    /// `expected`, failing with [`RepositoryError::Stale`] otherwise.
+    async fn update(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        role: Role,
+        expected: NaiveDateTime,
+    ) -> Result<Role, RepositoryError>;
+    /// Soft deletes the role, guarded by `expected` like [`Self::update`].
+    async fn delete(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        expected: NaiveDateTime,
+    ) -> Result<Role, RepositoryError>;
+    async fn restore(&self, tenant: Tenant, role_id: Uuid) -> Result<Role, RepositoryError>;
+    /// The roles `role_id` inherits from, nearest first. The walk stops at a
+    /// soft-deleted parent unless `include_deleted` is set.
+    async fn find_ancestors(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<Vec<Role>, RepositoryError>;
+    /// The live roles assigned to any of `user_ids`, each paired with the
+    /// user it is assigned to and ordered by code.
+    async fn find_by_users(
+        &self,
+        tenant: Tenant,
+        user_ids: &[Uuid],
+    ) -> Result<Vec<(Uuid, Role)>, RepositoryError>;
+    /// The ids of the live users the role is assigned to.
+    async fn find_user_ids(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Uuid>, RepositoryError>;
+    async fn assign(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError>;
+    async fn unassign(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError>;
+    /// Soft deletes the assignment of a role to a user, so that [`Self::resume`]
+    /// can bring it back.
+    async fn suspend(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError>;
+    async fn resume(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError>;
+    /// The ids of the live users whose assignment of the role is suspended.
+    async fn find_suspended_user_ids(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Uuid>, RepositoryError>;
+}
+
+/// Lookup of organizations, implemented by
+/// [`organization_repository::OrganizationRepository`].
+#[async_trait]
+pub trait OrganizationStore: Send + Sync {
+    /// The organization a user belongs to, for requests that identify the
+    /// user by a token rather than by an access token or header.
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Organization, RepositoryError>;
+}
+
+/// Persistence of permissions and their assignment to roles, implemented by
+/// [`permission_repository::PermissionRepository`].
+#[async_trait]
+pub trait PermissionStore: Send + Sync {
+    async fn find_all(&self, tenant: Tenant) -> Result<Vec<Permission>, RepositoryError>;
+    async fn find_by_id(
+        &self,
+        tenant: Tenant,
+        permission_id: Uuid,
+    ) -> Result<Permission, RepositoryError>;
+    async fn create(
+        &self,
+        tenant: Tenant,
+        permission: NewPermission,
+    ) -> Result<Permission, RepositoryError>;
+    async fn find_by_role(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Permission>, RepositoryError>;
+    async fn assign(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        permission_id: Uuid,
+    ) -> Result<usize, RepositoryError>;
+    async fn unassign(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        permission_id: Uuid,
+    ) -> Result<usize, RepositoryError>;
+    async fn find_codes_by_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+    ) -> Result<Vec<String>, RepositoryError>;
+}
+
+/// Persistence of refresh tokens, implemented by
+/// [`refresh_token_repository::RefreshTokenRepository`].
+#[async_trait]
+pub trait RefreshTokenStore: Send + Sync {
+    async fn create(&self, token: NewRefreshToken) -> Result<RefreshToken, RepositoryError>;
+    async fn find_by_hash(&self, token_hash: &str) -> Result<RefreshToken, RepositoryError>;
+    /// Atomically marks `token_id` as used and stores its successor. Returns
+    /// `None` without storing anything when the token was already used or
+    /// revoked, which happens when the same token is presented twice.
+    async fn rotate(
+        &self,
+        token_id: Uuid,
+        successor: NewRefreshToken,
+    ) -> Result<Option<RefreshToken>, RepositoryError>;
+    async fn revoke_family(&self, family_id: Uuid) -> Result<usize, RepositoryError>;
+    async fn revoke_user(&self, user_id: Uuid) -> Result<usize, RepositoryError>;
+}
+
+/// Persistence of password reset tokens, implemented by
+/// [`password_reset_repository::PasswordResetRepository`].
+#[async_trait]
+pub trait PasswordResetStore: Send + Sync {
+    async fn create(
+        &self,
+        token: NewPasswordResetToken,
+    ) -> Result<PasswordResetToken, RepositoryError>;
+    async fn find_by_hash(&self, token_hash: &str) -> Result<PasswordResetToken, RepositoryError>;
+    /// Marks the token as used. Returns `false` when it had already been used.
+    async fn consume(&self, token_id: Uuid) -> Result<bool, RepositoryError>;
+    /// Marks every outstanding token of the user as used.
+    async fn invalidate_user(&self, user_id: Uuid) -> Result<usize, RepositoryError>;
+}
+
+/// Persistence of email verification tokens, implemented by
+/// [`email_verification_repository::EmailVerificationRepository`].
+#[async_trait]
+pub trait EmailVerificationStore: Send + Sync {
+    async fn create(
+        &self,
+        token: NewEmailVerificationToken,
+    ) -> Result<EmailVerificationToken, RepositoryError>;
+    async fn find_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<EmailVerificationToken, RepositoryError>;
+    /// Marks the token as used. Returns `false` when it had already been used.
+    async fn consume(&self, token_id: Uuid) -> Result<bool, RepositoryError>;
+    /// Marks every outstanding token of the user as used.
+    async fn invalidate_user(&self, user_id: Uuid) -> Result<usize, RepositoryError>;
+}
+
+/// Persistence of the audit log, implemented by
+/// [`audit_repository::AuditRepository`]. Events are only ever appended, and
+/// each belongs to the organization of `tenant`.
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    async fn record(
+        &self,
+        tenant: Tenant,
+        event: NewAuditEvent,
+    ) -> Result<AuditEvent, RepositoryError>;
+    async fn find_all(
+        &self,
+        tenant: Tenant,
+        filter: &AuditListQuery,
+    ) -> Result<(Vec<AuditEvent>, i64), RepositoryError>;
+}
+
+/// The stores available inside a transaction, which see each other's writes
+/// and commit or roll back together.
+#[async_trait]
+pub trait Transaction: Send + Sync {
+    fn organizations(&self) -> &dyn OrganizationStore;
+    fn users(&self) -> &dyn UserStore;
+    fn roles(&self) -> &dyn RoleStore;
+    fn permissions(&self) -> &dyn PermissionStore;
+    fn refresh_tokens(&self) -> &dyn RefreshTokenStore;
+    fn password_resets(&self) -> &dyn PasswordResetStore;
+    fn email_verifications(&self) -> &dyn EmailVerificationStore;
+    fn audit(&self) -> &dyn AuditStore;
+    async fn commit(&self) -> Result<(), RepositoryError>;
+    async fn rollback(&self) -> Result<(), RepositoryError>;
+}
+
+/// Starts transactions, implemented by [`unit_of_work::PgUnitOfWork`].
+#[async_trait]
+pub trait UnitOfWork: Send + Sync {
+    async fn begin(&self) -> Result<Arc<dyn Transaction>, RepositoryError>;
+}
+
+/// Runs `f` in a new transaction, committing when it succeeds and rolling
+/// back when it fails.
+pub async fn in_transaction<T, E, F, Fut>(unit_of_work: &dyn UnitOfWork, f: F) -> Result<T, E>
+where
+    F: FnOnce(Arc<dyn Transaction>) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<RepositoryError>,
+{
+    let tx = unit_of_work.begin().await?;
+    match f(tx.clone()).await {
+        Ok(value) => {
+            tx.commit().await?;
+            Ok(value)
+        }
+        Err(err) => {
+            if let Err(e) = tx.rollback().await {
+                tracing::warn!("Failed to roll back transaction: {:?}", e);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Builds an `ILIKE` pattern matching `term` anywhere, with LIKE wildcards in
+/// the term escaped so they match literally.
+pub fn contains_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+diesel::define_sql_function! {
+    /// SQL `lower()`, used to match the case-insensitive unique indexes.
+    fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text;
+}
+
+/// Maps the name of a violated unique constraint to the field it guards.
+fn unique_violation_field(constraint: Option<&str>) -> String {
+    match constraint {
+        Some("users_email_unique") => "email".to_string(),
+        Some("roles_code_unique") | Some("permissions_code_unique") => "code".to_string(),
+        Some(other) => other.to_string(),
+        None => "unknown".to_string(),
+    }
+}
@@ -0,0 +1,33 @@
+use crate::config::database::Database;
+use crate::models::organization::Organization;
+use crate::repositories::{OrganizationStore, RepositoryError};
+use crate::schema::{organizations, users};
+use async_trait::async_trait;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct OrganizationRepository {
+    pub db: Database,
+}
+
+impl OrganizationRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl OrganizationStore for OrganizationRepository {
+    async fn find_by_user(&self, user_id: Uuid) -> Result<Organization, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                organizations::table
+                    .inner_join(users::table)
+                    .filter(users::id.eq(user_id))
+                    .select(Organization::as_select())
+                    .first(conn)
+            })
+            .await
+    }
+}
@@ -0,0 +1,76 @@
+use crate::config::database::Database;
+use crate::models::password_reset::{NewPasswordResetToken, PasswordResetToken};
+use crate::repositories::{PasswordResetStore, RepositoryError};
+use crate::schema::password_reset_tokens::dsl::*;
+use async_trait::async_trait;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct PasswordResetRepository {
+    pub db: Database,
+}
+
+impl PasswordResetRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl PasswordResetStore for PasswordResetRepository {
+    async fn create(
+        &self,
+        token: NewPasswordResetToken,
+    ) -> Result<PasswordResetToken, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(password_reset_tokens)
+                    .values(&token)
+                    .returning(PasswordResetToken::as_returning())
+                    .get_result(conn)
+            })
+            .await
+    }
+
+    async fn find_by_hash(&self, hash: &str) -> Result<PasswordResetToken, RepositoryError> {
+        let hash = hash.to_string();
+        self.db
+            .run(move |conn| {
+                password_reset_tokens
+                    .filter(token_hash.eq(hash))
+                    .select(PasswordResetToken::as_select())
+                    .first(conn)
+            })
+            .await
+    }
+
+    async fn consume(&self, token_id: Uuid) -> Result<bool, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    password_reset_tokens
+                        .find(token_id)
+                        .filter(used_at.is_null()),
+                )
+                .set(used_at.eq(Some(chrono::Utc::now().naive_utc())))
+                .execute(conn)
+                .map(|updated| updated > 0)
+            })
+            .await
+    }
+
+    async fn invalidate_user(&self, owner: Uuid) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    password_reset_tokens
+                        .filter(user_id.eq(owner))
+                        .filter(used_at.is_null()),
+                )
+                .set(used_at.eq(Some(chrono::Utc::now().naive_utc())))
+                .execute(conn)
+            })
+            .await
+    }
+}
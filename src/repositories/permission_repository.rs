@@ -0,0 +1,178 @@
+use crate::config::database::Database;
+use crate::models::organization::Tenant;
+use crate::models::permission::{NewPermission, NewRolePermission, Permission};
+use crate::repositories::{PermissionStore, RepositoryError};
+use crate::schema::{permissions, role_permissions, roles, user_roles, users};
+use async_trait::async_trait;
+use diesel::dsl::{Eq, Filter};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct PermissionRepository {
+    pub db: Database,
+}
+
+impl PermissionRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// The permissions of `tenant`. Every query starts from here, so none
+    /// can reach another organization's rows.
+    fn of(tenant: Tenant) -> Filter<permissions::table, Eq<permissions::organization_id, Uuid>> {
+        permissions::table.filter(permissions::organization_id.eq(tenant.id()))
+    }
+}
+
+#[async_trait]
+impl PermissionStore for PermissionRepository {
+    async fn find_all(&self, tenant: Tenant) -> Result<Vec<Permission>, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                Self::of(tenant)
+                    .order(permissions::code.asc())
+                    .load::<Permission>(conn)
+            })
+            .await
+    }
+
+    async fn find_by_id(
+        &self,
+        tenant: Tenant,
+        permission_id: Uuid,
+    ) -> Result<Permission, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                Self::of(tenant)
+                    .filter(permissions::id.eq(permission_id))
+                    .get_result::<Permission>(conn)
+            })
+            .await
+    }
+
+    async fn create(
+        &self,
+        tenant: Tenant,
+        permission: NewPermission,
+    ) -> Result<Permission, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(permissions::table)
+                    .values((&permission, permissions::organization_id.eq(tenant.id())))
+                    .get_result::<Permission>(conn)
+            })
+            .await
+    }
+
+    async fn find_by_role(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Permission>, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                Self::of(tenant)
+                    .inner_join(role_permissions::table)
+                    .filter(role_permissions::organization_id.eq(tenant.id()))
+                    .filter(role_permissions::role_id.eq(role_id))
+                    .order(permissions::code.asc())
+                    .select(Permission::as_select())
+                    .load::<Permission>(conn)
+            })
+            .await
+    }
+
+    /// Attaches a permission to a role. Attaching an already attached
+    /// permission is a no-op. The role and the permission must both belong
+    /// to `tenant`, which the foreign keys enforce.
+    async fn assign(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        permission_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(role_permissions::table)
+                    .values(&NewRolePermission {
+                        role_id,
+                        permission_id,
+                        organization_id: tenant.id(),
+                    })
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+            })
+            .await
+    }
+
+    async fn unassign(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        permission_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::delete(
+                    role_permissions::table
+                        .filter(role_permissions::organization_id.eq(tenant.id()))
+                        .filter(role_permissions::role_id.eq(role_id))
+                        .filter(role_permissions::permission_id.eq(permission_id)),
+                )
+                .execute(conn)
+            })
+            .await
+    }
+
+    /// Returns the distinct permission codes granted to a user through any
+    /// of their roles and the roles those inherit from. Soft-deleted users
+    /// and roles grant nothing, and a soft-deleted parent cuts off the roles
+    /// above it.
+    async fn find_codes_by_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+    ) -> Result<Vec<String>, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                let mut role_ids = roles::table
+                    .inner_join(user_roles::table.on(user_roles::role_id.eq(roles::id)))
+                    .inner_join(users::table.on(users::id.eq(user_roles::user_id)))
+                    .filter(users::id.eq(user_id))
+                    .filter(user_roles::deleted_at.is_null())
+                    .filter(users::organization_id.eq(tenant.id()))
+                    .filter(roles::organization_id.eq(tenant.id()))
+                    .filter(users::deleted_at.is_null())
+                    .filter(roles::deleted_at.is_null())
+                    .select(roles::id)
+                    .load::<Uuid>(conn)?;
+
+                let mut frontier = role_ids.clone();
+                while !frontier.is_empty() {
+                    let parents = roles::table
+                        .filter(roles::id.eq_any(&frontier))
+                        .filter(roles::parent_role_id.is_not_null())
+                        .select(roles::parent_role_id.assume_not_null())
+                        .load::<Uuid>(conn)?;
+                    frontier = roles::table
+                        .filter(roles::organization_id.eq(tenant.id()))
+                        .filter(roles::id.eq_any(parents))
+                        .filter(roles::id.ne_all(&role_ids))
+                        .filter(roles::deleted_at.is_null())
+                        .select(roles::id)
+                        .load::<Uuid>(conn)?;
+                    role_ids.extend(&frontier);
+                }
+
+                Self::of(tenant)
+                    .inner_join(role_permissions::table)
+                    .filter(role_permissions::role_id.eq_any(role_ids))
+                    .select(permissions::code)
+                    .distinct()
+                    .order(permissions::code.asc())
+                    .load::<String>(conn)
+            })
+            .await
+    }
+}
@@ -0,0 +1,101 @@
+use crate::config::database::Database;
+use crate::models::refresh_token::{NewRefreshToken, RefreshToken};
+use crate::repositories::{RefreshTokenStore, RepositoryError};
+use crate::schema::refresh_tokens::dsl::*;
+use async_trait::async_trait;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct RefreshTokenRepository {
+    pub db: Database,
+}
+
+impl RefreshTokenRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenStore for RefreshTokenRepository {
+    async fn create(&self, token: NewRefreshToken) -> Result<RefreshToken, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(refresh_tokens)
+                    .values(&token)
+                    .returning(RefreshToken::as_returning())
+                    .get_result(conn)
+            })
+            .await
+    }
+
+    async fn find_by_hash(&self, hash: &str) -> Result<RefreshToken, RepositoryError> {
+        let hash = hash.to_string();
+        self.db
+            .run(move |conn| {
+                refresh_tokens
+                    .filter(token_hash.eq(hash))
+                    .select(RefreshToken::as_select())
+                    .first(conn)
+            })
+            .await
+    }
+
+    async fn rotate(
+        &self,
+        token_id: Uuid,
+        successor: NewRefreshToken,
+    ) -> Result<Option<RefreshToken>, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                conn.transaction(|conn| {
+                    let claimed = diesel::update(
+                        refresh_tokens
+                            .find(token_id)
+                            .filter(used_at.is_null())
+                            .filter(revoked_at.is_null()),
+                    )
+                    .set(used_at.eq(Some(chrono::Utc::now().naive_utc())))
+                    .execute(conn)?;
+                    if claimed == 0 {
+                        return Ok(None);
+                    }
+                    diesel::insert_into(refresh_tokens)
+                        .values(&successor)
+                        .returning(RefreshToken::as_returning())
+                        .get_result(conn)
+                        .map(Some)
+                })
+            })
+            .await
+    }
+
+    async fn revoke_family(&self, family: Uuid) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    refresh_tokens
+                        .filter(family_id.eq(family))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(Some(chrono::Utc::now().naive_utc())))
+                .execute(conn)
+            })
+            .await
+    }
+
+    async fn revoke_user(&self, owner: Uuid) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    refresh_tokens
+                        .filter(user_id.eq(owner))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(Some(chrono::Utc::now().naive_utc())))
+                .execute(conn)
+            })
+            .await
+    }
+}
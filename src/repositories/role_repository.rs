@@ -1,41 +1,413 @@
-use crate::config::database::DbPool;
-use crate::models::role::{NewRole, Role};
+use crate::config::database::Database;
+use crate::models::organization::Tenant;
+use crate::models::query::{Pagination, RoleListQuery, RoleSortField, SortDirection};
+use crate::models::role::{NewRole, NewUserRole, Role};
+use crate::repositories::{LockMode, RepositoryError, RoleStore, contains_pattern};
 use crate::schema::roles::dsl::*;
+use crate::schema::{roles, user_roles, users};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use diesel::dsl::{Eq, Filter};
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel::result::Error;
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct RoleRepository {
-    pub pool: DbPool,
+    pub db: Database,
 }
 impl RoleRepository {
-    pub fn new(pool: DbPool) -> Self {
-        Self { pool }
+    pub fn new(db: Database) -> Self {
+        Self { db }
     }
 
-    pub fn find_all(&self) -> Result<Vec<Role>, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        roles.load::<Role>(&mut conn)
+    /// The roles of `tenant`. Every query starts from here, so none can
+    /// reach another organization's rows.
+    fn of(tenant: Tenant) -> Filter<roles::table, Eq<organization_id, Uuid>> {
+        roles.filter(organization_id.eq(tenant.id()))
     }
-    pub fn find_by_id(&self, role_id: Uuid) -> Result<Role, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        roles.find(role_id).get_result::<Role>(&mut conn)
+
+    fn filtered(tenant: Tenant, filter: &RoleListQuery) -> roles::BoxedQuery<'static, Pg> {
+        let mut query = Self::of(tenant).into_boxed();
+        if !filter.include_deleted {
+            query = query.filter(deleted_at.is_null());
+        }
+        if let Some(term) = &filter.code {
+            query = query.filter(code.ilike(contains_pattern(term)));
+        }
+        if let Some(term) = &filter.name {
+            query = query.filter(name.ilike(contains_pattern(term)));
+        }
+        if let Some(from) = filter.created_from {
+            query = query.filter(created_at.ge(from.naive_utc()));
+        }
+        if let Some(to) = filter.created_to {
+            query = query.filter(created_at.le(to.naive_utc()));
+        }
+        query
+    }
+
+    /// The live users the role is assigned to, or whose assignment of it is
+    /// suspended.
+    fn user_ids(
+        tenant: Tenant,
+        role_id: Uuid,
+        suspended: bool,
+    ) -> user_roles::BoxedQuery<'static, Pg, diesel::sql_types::Uuid> {
+        let query = user_roles::table
+            .filter(user_roles::organization_id.eq(tenant.id()))
+            .filter(user_roles::role_id.eq(role_id))
+            .filter(
+                user_roles::user_id.eq_any(
+                    users::table
+                        .filter(users::organization_id.eq(tenant.id()))
+                        .filter(users::deleted_at.is_null())
+                        .select(users::id),
+                ),
+            )
+            .order(user_roles::user_id.asc())
+            .select(user_roles::user_id)
+            .into_boxed();
+        if suspended {
+            query.filter(user_roles::deleted_at.is_not_null())
+        } else {
+            query.filter(user_roles::deleted_at.is_null())
+        }
+    }
+
+    /// Passes `written` through, unless nothing was written because the
+    /// role does not exist (any more), which fails with `NotFound`.
+    /// `None` is left for a row whose `updated_at` no longer matched.
+    fn check_stale(
+        conn: &mut PgConnection,
+        tenant: Tenant,
+        role_id: Uuid,
+        written: Option<Role>,
+    ) -> QueryResult<Option<Role>> {
+        if written.is_none() {
+            Self::of(tenant)
+                .filter(id.eq(role_id))
+                .filter(deleted_at.is_null())
+                .select(id)
+                .first::<Uuid>(conn)?;
+        }
+        Ok(written)
+    }
+}
+
+#[async_trait]
+impl RoleStore for RoleRepository {
+    /// Returns one page of roles matching `filter` together with the total
+    /// number of matching rows.
+    async fn find_all(
+        &self,
+        tenant: Tenant,
+        filter: &RoleListQuery,
+    ) -> Result<(Vec<Role>, i64), RepositoryError> {
+        let filter = filter.clone();
+        self.db
+            .run(move |conn| {
+                let pagination = Pagination::new(filter.page, filter.limit);
+
+                let total = Self::filtered(tenant, &filter)
+                    .count()
+                    .get_result::<i64>(conn)?;
+
+                let mut query = Self::filtered(tenant, &filter);
+                query = match (filter.sort_by, filter.sort_dir) {
+                    (RoleSortField::Name, SortDirection::Asc) => query.order(name.asc()),
+                    (RoleSortField::Name, SortDirection::Desc) => query.order(name.desc()),
+                    (RoleSortField::Code, SortDirection::Asc) => query.order(code.asc()),
+                    (RoleSortField::Code, SortDirection::Desc) => query.order(code.desc()),
+                    (RoleSortField::CreatedAt, SortDirection::Asc) => query.order(created_at.asc()),
+                    (RoleSortField::CreatedAt, SortDirection::Desc) => {
+                        query.order(created_at.desc())
+                    }
+                    (RoleSortField::UpdatedAt, SortDirection::Asc) => query.order(updated_at.asc()),
+                    (RoleSortField::UpdatedAt, SortDirection::Desc) => {
+                        query.order(updated_at.desc())
+                    }
+                };
+                let data = query
+                    .then_order_by(id.asc())
+                    .limit(pagination.limit)
+                    .offset(pagination.offset())
+                    .load::<Role>(conn)?;
+
+                Ok((data, total))
+            })
+            .await
+    }
+
+    async fn find_by_id(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<Role, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                let mut query = Self::of(tenant).filter(id.eq(role_id)).into_boxed();
+                if !include_deleted {
+                    query = query.filter(deleted_at.is_null());
+                }
+                query.get_result::<Role>(conn)
+            })
+            .await
+    }
+
+    async fn lock(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        mode: LockMode,
+    ) -> Result<Role, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                let query = Self::of(tenant)
+                    .filter(id.eq(role_id))
+                    .filter(deleted_at.is_null());
+                match mode {
+                    LockMode::Share => query.for_share().get_result::<Role>(conn),
+                    LockMode::Update => query.for_update().get_result::<Role>(conn),
+                }
+            })
+            .await
+    }
+
+    async fn create(&self, tenant: Tenant, role: NewRole) -> Result<Role, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(roles)
+                    .values((&role, organization_id.eq(tenant.id())))
+                    .get_result::<Role>(conn)
+            })
+            .await
+    }
+
+    async fn update(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        mut role: Role,
+        expected: NaiveDateTime,
+    ) -> Result<Role, RepositoryError> {
+        // Rows never move to another organization
+        role.organization_id = tenant.id();
+        self.db
+            .run(move |conn| {
+                let updated = diesel::update(
+                    Self::of(tenant)
+                        .filter(id.eq(role_id))
+                        .filter(deleted_at.is_null())
+                        .filter(updated_at.eq(expected)),
+                )
+                .set(&role)
+                .get_result::<Role>(conn)
+                .optional()?;
+                Self::check_stale(conn, tenant, role_id, updated)
+            })
+            .await?
+            .ok_or(RepositoryError::Stale)
+    }
+
+    /// Soft deletes the role by stamping `deleted_at`.
+    async fn delete(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        expected: NaiveDateTime,
+    ) -> Result<Role, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                let now = chrono::Utc::now().naive_utc();
+                let deleted = diesel::update(
+                    Self::of(tenant)
+                        .filter(id.eq(role_id))
+                        .filter(deleted_at.is_null())
+                        .filter(updated_at.eq(expected)),
+                )
+                .set((deleted_at.eq(Some(now)), updated_at.eq(now)))
+                .get_result::<Role>(conn)
+                .optional()?;
+                Self::check_stale(conn, tenant, role_id, deleted)
+            })
+            .await?
+            .ok_or(RepositoryError::Stale)
+    }
+
+    async fn restore(&self, tenant: Tenant, role_id: Uuid) -> Result<Role, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    Self::of(tenant)
+                        .filter(id.eq(role_id))
+                        .filter(deleted_at.is_not_null()),
+                )
+                .set((
+                    deleted_at.eq(None::<NaiveDateTime>),
+                    updated_at.eq(chrono::Utc::now().naive_utc()),
+                ))
+                .get_result(conn)
+            })
+            .await
+    }
+
+    async fn find_ancestors(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<Vec<Role>, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                let mut ancestors: Vec<Role> = Vec::new();
+                let mut parent = Self::of(tenant)
+                    .filter(id.eq(role_id))
+                    .select(parent_role_id)
+                    .first::<Option<Uuid>>(conn)?;
+                // The visited check only guards against a cycle written
+                // by concurrent updates
+                while let Some(parent_id) = parent {
+                    if parent_id == role_id || ancestors.iter().any(|r| r.id == parent_id) {
+                        break;
+                    }
+                    let mut query = Self::of(tenant).filter(id.eq(parent_id)).into_boxed();
+                    if !include_deleted {
+                        query = query.filter(deleted_at.is_null());
+                    }
+                    let Some(role) = query.first::<Role>(conn).optional()? else {
+                        break;
+                    };
+                    parent = role.parent_role_id;
+                    ancestors.push(role);
+                }
+                Ok(ancestors)
+            })
+            .await
+    }
+
+    async fn find_by_users(
+        &self,
+        tenant: Tenant,
+        user_ids: &[Uuid],
+    ) -> Result<Vec<(Uuid, Role)>, RepositoryError> {
+        let user_ids = user_ids.to_vec();
+        self.db
+            .run(move |conn| {
+                Self::of(tenant)
+                    .inner_join(user_roles::table.on(user_roles::role_id.eq(id)))
+                    .filter(user_roles::user_id.eq_any(user_ids))
+                    .filter(user_roles::deleted_at.is_null())
+                    .filter(deleted_at.is_null())
+                    .order((user_roles::user_id.asc(), code.asc()))
+                    .select((user_roles::user_id, roles::all_columns))
+                    .load::<(Uuid, Role)>(conn)
+            })
+            .await
     }
-    pub fn create(&self, role: NewRole) -> Result<Role, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        diesel::insert_into(roles)
-            .values(&role)
-            .get_result::<Role>(&mut conn)
+
+    async fn find_user_ids(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Uuid>, RepositoryError> {
+        self.db
+            .run(move |conn| Self::user_ids(tenant, role_id, false).load::<Uuid>(conn))
+            .await
     }
-    pub fn update(&self, role_id: Uuid, role: Role) -> Result<Role, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        diesel::update(roles.find(role_id))
-            .set(&role)
-            .get_result::<Role>(&mut conn)
+
+    /// Assigns a role to a user. Assigning an already assigned role is a
+    /// no-op.
+    async fn assign(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(user_roles::table)
+                    .values(&NewUserRole {
+                        user_id,
+                        role_id,
+                        organization_id: tenant.id(),
+                    })
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+            })
+            .await
     }
-    pub fn delete(&self, role_id: Uuid) -> Result<Role, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        diesel::delete(roles.find(role_id)).get_result(&mut conn)
+
+    async fn unassign(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::delete(
+                    user_roles::table
+                        .filter(user_roles::organization_id.eq(tenant.id()))
+                        .filter(user_roles::user_id.eq(user_id))
+                        .filter(user_roles::role_id.eq(role_id))
+                        .filter(user_roles::deleted_at.is_null()),
+                )
+                .execute(conn)
+            })
+            .await
+    }
+
+    async fn suspend(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    user_roles::table
+                        .filter(user_roles::organization_id.eq(tenant.id()))
+                        .filter(user_roles::user_id.eq(user_id))
+                        .filter(user_roles::role_id.eq(role_id))
+                        .filter(user_roles::deleted_at.is_null()),
+                )
+                .set(user_roles::deleted_at.eq(diesel::dsl::now))
+                .execute(conn)
+            })
+            .await
+    }
+
+    async fn resume(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<usize, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    user_roles::table
+                        .filter(user_roles::organization_id.eq(tenant.id()))
+                        .filter(user_roles::user_id.eq(user_id))
+                        .filter(user_roles::role_id.eq(role_id))
+                        .filter(user_roles::deleted_at.is_not_null()),
+                )
+                .set(user_roles::deleted_at.eq(None::<NaiveDateTime>))
+                .execute(conn)
+            })
+            .await
+    }
+
+    async fn find_suspended_user_ids(
+        &self,
+        tenant: Tenant,
+        role_id: Uuid,
+    ) -> Result<Vec<Uuid>, RepositoryError> {
+        self.db
+            .run(move |conn| Self::user_ids(tenant, role_id, true).load::<Uuid>(conn))
+            .await
     }
 }
@@ -0,0 +1,101 @@
+use crate::config::database::Database;
+use crate::repositories::audit_repository::AuditRepository;
+use crate::repositories::email_verification_repository::EmailVerificationRepository;
+use crate::repositories::organization_repository::OrganizationRepository;
+use crate::repositories::password_reset_repository::PasswordResetRepository;
+use crate::repositories::permission_repository::PermissionRepository;
+use crate::repositories::refresh_token_repository::RefreshTokenRepository;
+use crate::repositories::role_repository::RoleRepository;
+use crate::repositories::user_repository::UserRepository;
+use crate::repositories::{
+    AuditStore, EmailVerificationStore, OrganizationStore, PasswordResetStore, PermissionStore,
+    RefreshTokenStore, RepositoryError, RoleStore, Transaction, UnitOfWork, UserStore,
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct PgUnitOfWork {
+    pub db: Database,
+}
+
+impl PgUnitOfWork {
+    pub fn new(db: Database) -> Self {
+        PgUnitOfWork { db }
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for PgUnitOfWork {
+    async fn begin(&self) -> Result<Arc<dyn Transaction>, RepositoryError> {
+        let db = self.db.begin().await?;
+        Ok(Arc::new(PgTransaction {
+            organizations: OrganizationRepository::new(db.clone()),
+            users: UserRepository::new(db.clone()),
+            roles: RoleRepository::new(db.clone()),
+            permissions: PermissionRepository::new(db.clone()),
+            refresh_tokens: RefreshTokenRepository::new(db.clone()),
+            password_resets: PasswordResetRepository::new(db.clone()),
+            email_verifications: EmailVerificationRepository::new(db.clone()),
+            audit: AuditRepository::new(db.clone()),
+            db,
+        }))
+    }
+}
+
+/// The repositories of one PostgreSQL transaction, all running on its
+/// connection.
+pub struct PgTransaction {
+    db: Database,
+    organizations: OrganizationRepository,
+    users: UserRepository,
+    roles: RoleRepository,
+    permissions: PermissionRepository,
+    refresh_tokens: RefreshTokenRepository,
+    password_resets: PasswordResetRepository,
+    email_verifications: EmailVerificationRepository,
+    audit: AuditRepository,
+}
+
+#[async_trait]
+impl Transaction for PgTransaction {
+    fn organizations(&self) -> &dyn OrganizationStore {
+        &self.organizations
+    }
+
+    fn users(&self) -> &dyn UserStore {
+        &self.users
+    }
+
+    fn roles(&self) -> &dyn RoleStore {
+        &self.roles
+    }
+
+    fn permissions(&self) -> &dyn PermissionStore {
+        &self.permissions
+    }
+
+    fn refresh_tokens(&self) -> &dyn RefreshTokenStore {
+        &self.refresh_tokens
+    }
+
+    fn password_resets(&self) -> &dyn PasswordResetStore {
+        &self.password_resets
+    }
+
+    fn email_verifications(&self) -> &dyn EmailVerificationStore {
+        &self.email_verifications
+    }
+
+    fn audit(&self) -> &dyn AuditStore {
+        &self.audit
+    }
+
+    async fn commit(&self) -> Result<(), RepositoryError> {
+        self.db.commit().await
+    }
+
+    async fn rollback(&self) -> Result<(), RepositoryError> {
+        self.db.rollback().await
+    }
+}
@@ -1,45 +1,258 @@
-use crate::config::database::DbPool;
+use crate::config::database::Database;
+use crate::models::organization::Tenant;
+use crate::models::query::{Pagination, SortDirection, UserListQuery, UserSortField};
 use crate::models::user::{NewUser, User};
+use crate::repositories::{LockMode, RepositoryError, UserStore, contains_pattern, lower};
 use crate::schema::users::dsl::*;
+use crate::schema::{user_roles, users};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use diesel::dsl::{Eq, Filter};
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel::result::Error;
 use uuid::Uuid;
 
+#[derive(Clone)]
 pub struct UserRepository {
-    pub pool: DbPool,
+    pub db: Database,
 }
 
 impl UserRepository {
-    pub fn new(pool: DbPool) -> Self {
-        UserRepository { pool }
+    pub fn new(db: Database) -> Self {
+        UserRepository { db }
     }
 
-    pub fn get_users(&self) -> Result<Vec<User>, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        users.load::<User>(&mut conn)
+    /// The users of `tenant`. Every query starts from here, so none can
+    /// reach another organization's rows.
+    fn of(tenant: Tenant) -> Filter<users::table, Eq<organization_id, Uuid>> {
+        users.filter(organization_id.eq(tenant.id()))
     }
 
-    pub fn create_user(&self, new_user: NewUser) -> Result<User, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        diesel::insert_into(users)
-            .values(&new_user)
-            .get_result::<User>(&mut conn)
+    fn filtered(tenant: Tenant, filter: &UserListQuery) -> users::BoxedQuery<'static, Pg> {
+        let mut query = Self::of(tenant).into_boxed();
+        if !filter.include_deleted {
+            query = query.filter(deleted_at.is_null());
+        }
+        if let Some(role) = filter.role_id {
+            query = query.filter(
+                id.eq_any(
+                    user_roles::table
+                        .filter(user_roles::role_id.eq(role))
+                        .filter(user_roles::deleted_at.is_null())
+                        .select(user_roles::user_id),
+                ),
+            );
+        }
+        if let Some(term) = &filter.email {
+            query = query.filter(email.ilike(contains_pattern(term)));
+        }
+        if let Some(term) = &filter.name {
+            query = query.filter(name.ilike(contains_pattern(term)));
+        }
+        if let Some(from) = filter.created_from {
+            query = query.filter(created_at.ge(from.naive_utc()));
+        }
+        if let Some(to) = filter.created_to {
+            query = query.filter(created_at.le(to.naive_utc()));
+        }
+        query
     }
 
-    pub fn get_user(&self, user_id: Uuid) -> Result<User, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        users.find(user_id).get_result::<User>(&mut conn)
+    /// Passes `written` through, unless nothing was written because the
+    /// user does not exist (any more), which fails with `NotFound`.
+    /// `None` is left for a row whose `updated_at` no longer matched.
+    fn check_stale(
+        conn: &mut PgConnection,
+        tenant: Tenant,
+        user_id: Uuid,
+        written: Option<User>,
+    ) -> QueryResult<Option<User>> {
+        if written.is_none() {
+            Self::of(tenant)
+                .filter(id.eq(user_id))
+                .filter(deleted_at.is_null())
+                .select(id)
+                .first::<Uuid>(conn)?;
+        }
+        Ok(written)
+    }
+}
+
+#[async_trait]
+impl UserStore for UserRepository {
+    /// Returns one page of users matching `filter` together with the total
+    /// number of matching rows.
+    async fn get_users(
+        &self,
+        tenant: Tenant,
+        filter: &UserListQuery,
+    ) -> Result<(Vec<User>, i64), RepositoryError> {
+        let filter = filter.clone();
+        self.db
+            .run(move |conn| {
+                let pagination = Pagination::new(filter.page, filter.limit);
+
+                let total = Self::filtered(tenant, &filter)
+                    .count()
+                    .get_result::<i64>(conn)?;
+
+                let mut query = Self::filtered(tenant, &filter);
+                query = match (filter.sort_by, filter.sort_dir) {
+                    (UserSortField::Name, SortDirection::Asc) => query.order(name.asc()),
+                    (UserSortField::Name, SortDirection::Desc) => query.order(name.desc()),
+                    (UserSortField::Email, SortDirection::Asc) => query.order(email.asc()),
+                    (UserSortField::Email, SortDirection::Desc) => query.order(email.desc()),
+                    (UserSortField::CreatedAt, SortDirection::Asc) => query.order(created_at.asc()),
+                    (UserSortField::CreatedAt, SortDirection::Desc) => {
+                        query.order(created_at.desc())
+                    }
+                    (UserSortField::UpdatedAt, SortDirection::Asc) => query.order(updated_at.asc()),
+                    (UserSortField::UpdatedAt, SortDirection::Desc) => {
+                        query.order(updated_at.desc())
+                    }
+                };
+                let data = query
+                    .then_order_by(id.asc())
+                    .limit(pagination.limit)
+                    .offset(pagination.offset())
+                    .load::<User>(conn)?;
+
+                Ok((data, total))
+            })
+            .await
+    }
+
+    async fn create_user(
+        &self,
+        tenant: Tenant,
+        new_user: NewUser,
+    ) -> Result<User, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(users)
+                    .values((&new_user, organization_id.eq(tenant.id())))
+                    .get_result::<User>(conn)
+            })
+            .await
+    }
+
+    async fn get_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        include_deleted: bool,
+    ) -> Result<User, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                let mut query = Self::of(tenant).filter(id.eq(user_id)).into_boxed();
+                if !include_deleted {
+                    query = query.filter(deleted_at.is_null());
+                }
+                query.get_result::<User>(conn)
+            })
+            .await
+    }
+
+    async fn get_user_by_email(
+        &self,
+        tenant: Tenant,
+        user_email: &str,
+    ) -> Result<User, RepositoryError> {
+        let user_email = user_email.to_lowercase();
+        self.db
+            .run(move |conn| {
+                Self::of(tenant)
+                    .filter(lower(email).eq(user_email))
+                    .filter(deleted_at.is_null())
+                    .first::<User>(conn)
+            })
+            .await
+    }
+
+    async fn lock_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        mode: LockMode,
+    ) -> Result<User, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                let query = Self::of(tenant)
+                    .filter(id.eq(user_id))
+                    .filter(deleted_at.is_null());
+                match mode {
+                    LockMode::Share => query.for_share().get_result::<User>(conn),
+                    LockMode::Update => query.for_update().get_result::<User>(conn),
+                }
+            })
+            .await
+    }
+
+    async fn update_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        mut user_upd: User,
+        expected: NaiveDateTime,
+    ) -> Result<User, RepositoryError> {
+        // Rows never move to another organization
+        user_upd.organization_id = tenant.id();
+        self.db
+            .run(move |conn| {
+                let updated = diesel::update(
+                    Self::of(tenant)
+                        .filter(id.eq(user_id))
+                        .filter(deleted_at.is_null())
+                        .filter(updated_at.eq(expected)),
+                )
+                .set(&user_upd)
+                .get_result::<User>(conn)
+                .optional()?;
+                Self::check_stale(conn, tenant, user_id, updated)
+            })
+            .await?
+            .ok_or(RepositoryError::Stale)
     }
 
-    pub fn update_user(&self, user_id: Uuid, user_upd: User) -> Result<User, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        diesel::update(users.find(user_id))
-            .set(&user_upd)
-            .get_result::<User>(&mut conn)
+    /// Soft deletes the user by stamping `deleted_at`.
+    async fn delete_user(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+        expected: NaiveDateTime,
+    ) -> Result<User, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                let now = chrono::Utc::now().naive_utc();
+                let deleted = diesel::update(
+                    Self::of(tenant)
+                        .filter(id.eq(user_id))
+                        .filter(deleted_at.is_null())
+                        .filter(updated_at.eq(expected)),
+                )
+                .set((deleted_at.eq(Some(now)), updated_at.eq(now)))
+                .get_result::<User>(conn)
+                .optional()?;
+                Self::check_stale(conn, tenant, user_id, deleted)
+            })
+            .await?
+            .ok_or(RepositoryError::Stale)
     }
 
-    pub fn delete_user(&self, user_id: Uuid) -> Result<User, Error> {
-        let mut conn = self.pool.get().expect("Failed to get DB connection");
-        diesel::delete(users.find(user_id)).get_result(&mut conn)
+    async fn restore_user(&self, tenant: Tenant, user_id: Uuid) -> Result<User, RepositoryError> {
+        self.db
+            .run(move |conn| {
+                diesel::update(
+                    Self::of(tenant)
+                        .filter(id.eq(user_id))
+                        .filter(deleted_at.is_not_null()),
+                )
+                .set((
+                    deleted_at.eq(None::<NaiveDateTime>),
+                    updated_at.eq(chrono::Utc::now().naive_utc()),
+                ))
+                .get_result::<User>(conn)
+            })
+            .await
     }
 }
@@ -1,30 +1,115 @@
+use crate::extractors::REQUEST_ID_HEADER;
+use crate::handlers::audit_handler::{AuditHandler, get_audit_events_handler};
+use crate::handlers::auth_handler::{
+    AuthHandler, forgot_password_handler, login_handler, logout_all_handler, logout_handler,
+    refresh_handler, reset_password_handler, verify_email_handler,
+};
 use crate::handlers::role_handler::{
-    RoleHandler, create_role_handler, delete_role_handler, get_role_handler, get_roles_handler,
-    update_role_handler,
+    RoleHandler, assign_role_permission_handler, create_permission_handler, create_role_handler,
+    delete_role_handler, get_permissions_handler, get_role_ancestors_handler, get_role_handler,
+    get_role_permissions_handler, get_roles_handler, get_user_permissions_handler,
+    patch_role_handler, restore_role_handler, revoke_role_permission_handler, update_role_handler,
 };
 use crate::handlers::user_handler::{
-    UserHandler, create_user_handler, delete_user_handler, get_user_handler, get_users_handler,
-    update_user_handler,
+    UserHandler, assign_user_role_handler, change_password_handler, create_user_handler,
+    delete_user_handler, get_me_handler, get_user_handler, get_users_handler, patch_user_handler,
+    restore_user_handler, revoke_user_role_handler, update_me_handler, update_user_handler,
 };
+use crate::middlewares::auth_middleware::{require_auth, require_roles};
+use crate::models::role::{ROLE_ADMIN, ROLE_USER_MANAGER};
 use axum::Json;
 use axum::{
-    Router,
-    routing::{delete, get, post, put},
+    Router, middleware,
+    routing::{delete, get, patch, post, put},
 };
 use serde_json::json;
+use tower_http::cors::CorsLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
 
 #[derive(Clone)]
 pub struct AppState {
+    pub audit_handler: AuditHandler,
+    pub auth_handler: AuthHandler,
     pub user_handler: UserHandler,
     pub role_handler: RoleHandler,
 }
 
-pub fn create_router(user_handler: UserHandler, role_handler: RoleHandler) -> Router {
+pub fn create_router(
+    audit_handler: AuditHandler,
+    auth_handler: AuthHandler,
+    user_handler: UserHandler,
+    role_handler: RoleHandler,
+    cors: CorsLayer,
+) -> Router {
     let state = AppState {
+        audit_handler,
+        auth_handler,
         user_handler,
         role_handler,
     };
 
+    // User management is open to administrators and user managers
+    let user_routes = Router::new()
+        .route("/users", get(get_users_handler))
+        .route("/users", post(create_user_handler))
+        .route("/users/:id", get(get_user_handler))
+        .route("/users/:id", put(update_user_handler))
+        .route("/users/:id", patch(patch_user_handler))
+        .route("/users/:id", delete(delete_user_handler))
+        .route("/users/:id/restore", post(restore_user_handler))
+        .route("/users/:id/permissions", get(get_user_permissions_handler))
+        .route(
+            "/users/:id/roles/:role_id",
+            post(assign_user_role_handler).delete(revoke_user_role_handler),
+        )
+        .route_layer(middleware::from_fn(|req, next| {
+            require_roles(&[ROLE_ADMIN, ROLE_USER_MANAGER], req, next)
+        }));
+
+    // User managers need to read roles to assign them, only admins may change them
+    let role_read_routes = Router::new()
+        .route("/roles", get(get_roles_handler))
+        .route("/roles/:id", get(get_role_handler))
+        .route("/roles/:id/ancestors", get(get_role_ancestors_handler))
+        .route("/roles/:id/permissions", get(get_role_permissions_handler))
+        .route("/permissions", get(get_permissions_handler))
+        .route_layer(middleware::from_fn(|req, next| {
+            require_roles(&[ROLE_ADMIN, ROLE_USER_MANAGER], req, next)
+        }));
+    let role_write_routes = Router::new()
+        .route("/roles", post(create_role_handler))
+        .route("/roles/:id", put(update_role_handler))
+        .route("/roles/:id", patch(patch_role_handler))
+        .route("/roles/:id", delete(delete_role_handler))
+        .route("/roles/:id/restore", post(restore_role_handler))
+        .route(
+            "/roles/:id/permissions",
+            post(assign_role_permission_handler),
+        )
+        .route(
+            "/roles/:id/permissions/:permission_id",
+            delete(revoke_role_permission_handler),
+        )
+        .route("/permissions", post(create_permission_handler))
+        .route("/audit", get(get_audit_events_handler))
+        .route_layer(middleware::from_fn(|req, next| {
+            require_roles(&[ROLE_ADMIN], req, next)
+        }));
+
+    // Sessions and the caller's own profile, for any authenticated user
+    let session_routes = Router::new()
+        .route("/auth/logout-all", post(logout_all_handler))
+        .route("/me", get(get_me_handler).patch(update_me_handler))
+        .route("/me/password", post(change_password_handler));
+
+    let protected = Router::new()
+        .merge(session_routes)
+        .merge(user_routes)
+        .merge(role_read_routes)
+        .merge(role_write_routes)
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_auth));
+
     Router::new()
         .route(
             "/health",
@@ -35,17 +120,17 @@ pub fn create_router(user_handler: UserHandler, role_handler: RoleHandler) -> Ro
                 }))
             }),
         )
-        // User routes
-        .route("/users", get(get_users_handler))
-        .route("/users", post(create_user_handler))
-        .route("/users/:id", get(get_user_handler))
-        .route("/users/:id", put(update_user_handler))
-        .route("/users/:id", delete(delete_user_handler))
-        // Role routes
-        .route("/roles", get(get_roles_handler))
-        .route("/roles", post(create_role_handler))
-        .route("/roles/:id", get(get_role_handler))
-        .route("/roles/:id", put(update_role_handler))
-        .route("/roles/:id", delete(delete_role_handler))
+        // Auth routes
+        .route("/auth/login", post(login_handler))
+        .route("/auth/refresh", post(refresh_handler))
+        .route("/auth/logout", post(logout_handler))
+        .route("/auth/password/forgot", post(forgot_password_handler))
+        .route("/auth/password/reset", post(reset_password_handler))
+        .route("/auth/verify-email", post(verify_email_handler))
+        .merge(protected)
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(TraceLayer::new_for_http())
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+        .layer(cors)
         .with_state(state)
 }
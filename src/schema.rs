@@ -1,5 +1,97 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_events (id) {
+        id -> Uuid,
+        actor_id -> Nullable<Uuid>,
+        #[max_length = 50]
+        action -> Varchar,
+        #[max_length = 50]
+        entity_type -> Varchar,
+        entity_id -> Uuid,
+        changes -> Jsonb,
+        #[max_length = 100]
+        request_id -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        organization_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    email_verification_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 250]
+        email -> Varchar,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    organizations (id) {
+        id -> Uuid,
+        #[max_length = 250]
+        name -> Varchar,
+        #[max_length = 100]
+        slug -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    password_reset_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    permissions (id) {
+        id -> Uuid,
+        #[max_length = 250]
+        name -> Varchar,
+        #[max_length = 250]
+        code -> Varchar,
+        description -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        organization_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        family_id -> Uuid,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    role_permissions (role_id, permission_id) {
+        role_id -> Uuid,
+        permission_id -> Uuid,
+        created_at -> Timestamptz,
+        organization_id -> Uuid,
+    }
+}
+
 diesel::table! {
     roles (id) {
         id -> Uuid,
@@ -11,6 +103,18 @@ diesel::table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         deleted_at -> Nullable<Timestamptz>,
+        organization_id -> Uuid,
+        parent_role_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    user_roles (user_id, role_id) {
+        user_id -> Uuid,
+        role_id -> Uuid,
+        organization_id -> Uuid,
+        created_at -> Timestamptz,
+        deleted_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -22,16 +126,34 @@ diesel::table! {
         #[max_length = 250]
         email -> Varchar,
         password -> Text,
-        role_id -> Uuid,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         deleted_at -> Nullable<Timestamptz>,
+        email_verified_at -> Nullable<Timestamptz>,
+        organization_id -> Uuid,
     }
 }
 
-diesel::joinable!(users -> roles (role_id));
+diesel::joinable!(audit_events -> organizations (organization_id));
+diesel::joinable!(email_verification_tokens -> users (user_id));
+diesel::joinable!(password_reset_tokens -> users (user_id));
+diesel::joinable!(refresh_tokens -> users (user_id));
+diesel::joinable!(permissions -> organizations (organization_id));
+diesel::joinable!(role_permissions -> permissions (permission_id));
+diesel::joinable!(role_permissions -> roles (role_id));
+diesel::joinable!(roles -> organizations (organization_id));
+diesel::joinable!(user_roles -> organizations (organization_id));
+diesel::joinable!(users -> organizations (organization_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    audit_events,
+    email_verification_tokens,
+    organizations,
+    password_reset_tokens,
+    permissions,
+    refresh_tokens,
+    role_permissions,
     roles,
+    user_roles,
     users,
 );
@@ -0,0 +1,25 @@
+use crate::models::audit::AuditEvent;
+use crate::models::organization::Tenant;
+use crate::models::query::AuditListQuery;
+use crate::repositories::{AuditStore, RepositoryError};
+use std::sync::Arc;
+
+/// Read access to the audit log. Events are written by the services that
+/// make the changes, in the same transaction.
+pub struct AuditService {
+    pub repository: Arc<dyn AuditStore>,
+}
+
+impl AuditService {
+    pub fn new(repository: Arc<dyn AuditStore>) -> Self {
+        AuditService { repository }
+    }
+
+    pub async fn get_events(
+        &self,
+        tenant: Tenant,
+        filter: &AuditListQuery,
+    ) -> Result<(Vec<AuditEvent>, i64), RepositoryError> {
+        self.repository.find_all(tenant, filter).await
+    }
+}
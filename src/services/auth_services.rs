@@ -0,0 +1,1017 @@
+use crate::config::auth::AuthConfig;
+use crate::models::audit::{AuditAction, AuditContext};
+use crate::models::auth::{
+    AuthUser, Claims, LoginRequest, LoginResponse, ResetPasswordRequest, VerifyEmailRequest,
+};
+use crate::models::organization::Tenant;
+use crate::models::password_reset::NewPasswordResetToken;
+use crate::models::refresh_token::NewRefreshToken;
+use crate::models::user::User;
+use crate::notifications::{Notification, Notifier};
+use crate::repositories::{
+    LockMode, OrganizationStore, RefreshTokenStore, RepositoryError, RoleStore, UnitOfWork,
+    UserStore, in_transaction,
+};
+use crate::services::passwords::{hash_password, verify_password};
+use crate::services::tokens::{generate_token, hash_token};
+use crate::validation::{ValidationErrors, check_password};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum AuthError {
+    DatabaseError(String),
+    InvalidCredentials(String),
+    InvalidToken(String),
+    EmailNotVerified(String),
+    TokenError(String),
+    ValidationError(ValidationErrors),
+    HashError(String),
+    Unavailable(String),
+}
+
+impl From<RepositoryError> for AuthError {
+    fn from(err: RepositoryError) -> AuthError {
+        match err {
+            RepositoryError::NotFound => {
+                AuthError::InvalidCredentials("Invalid email or password".to_string())
+            }
+            RepositoryError::Unavailable(msg) => {
+                AuthError::Unavailable(format!("Database is unavailable: {}", msg))
+            }
+            RepositoryError::UniqueViolation(field) => {
+                AuthError::DatabaseError(format!("Unexpected conflict on {}", field))
+            }
+            RepositoryError::Stale => {
+                AuthError::DatabaseError("User was modified concurrently".to_string())
+            }
+            RepositoryError::Database(msg) => {
+                AuthError::DatabaseError(format!("Database error: {}", msg))
+            }
+        }
+    }
+}
+
+const INVALID_RESET_TOKEN: &str = "Invalid or expired reset token";
+
+pub struct AuthService {
+    pub repository: Arc<dyn UserStore>,
+    pub role_repository: Arc<dyn RoleStore>,
+    pub organization_repository: Arc<dyn OrganizationStore>,
+    pub refresh_token_repository: Arc<dyn RefreshTokenStore>,
+    pub unit_of_work: Arc<dyn UnitOfWork>,
+    pub notifier: Arc<dyn Notifier>,
+    pub config: AuthConfig,
+    /// Checked against when logging in with an unknown email address, so
+    /// that takes as long as a wrong password.
+    dummy_hash: String,
+}
+
+impl AuthService {
+    pub fn new(
+        repository: Arc<dyn UserStore>,
+        role_repository: Arc<dyn RoleStore>,
+        organization_repository: Arc<dyn OrganizationStore>,
+        refresh_token_repository: Arc<dyn RefreshTokenStore>,
+        unit_of_work: Arc<dyn UnitOfWork>,
+        notifier: Arc<dyn Notifier>,
+        config: AuthConfig,
+    ) -> Self {
+        let dummy_hash = bcrypt::hash(generate_token(), config.bcrypt_cost)
+            .expect("bcrypt cost is validated with the settings");
+        AuthService {
+            repository,
+            role_repository,
+            organization_repository,
+            refresh_token_repository,
+            unit_of_work,
+            notifier,
+            config,
+            dummy_hash,
+        }
+    }
+
+    pub async fn login(
+        &self,
+        tenant: Tenant,
+        input: LoginRequest,
+    ) -> Result<LoginResponse, AuthError> {
+        let user = match self
+            .repository
+            .get_user_by_email(tenant, &input.email)
+            .await
+        {
+            Ok(user) => Some(user),
+            Err(RepositoryError::NotFound) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        // An unknown address still costs a bcrypt check, so the response
+        // time does not tell which addresses have an account
+        let hash = user
+            .as_ref()
+            .map_or(&self.dummy_hash, |user| &user.password);
+        let valid = verify_password(input.password, hash.clone())
+            .await
+            .map_err(AuthError::HashError)?;
+        let Some(user) = user.filter(|_| valid) else {
+            return Err(AuthError::InvalidCredentials(
+                "Invalid email or password".to_string(),
+            ));
+        };
+        self.check_verified(&user)?;
+
+        self.issue_tokens(&user, Uuid::new_v4()).await
+    }
+
+    /// Exchanges a refresh token for a new access and refresh token pair.
+    ///
+    /// The presented token is single use. Presenting it again means it was
+    /// copied, so the whole family is revoked and its holder has to log in
+    /// again. The owner is checked as on login: they must not have been
+    /// deleted, and must have verified their email when that is required.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<LoginResponse, AuthError> {
+        let invalid = || AuthError::InvalidToken("Invalid or expired refresh token".to_string());
+        let token = self
+            .refresh_token_repository
+            .find_by_hash(&hash_token(refresh_token))
+            .await
+            .map_err(|e| match e {
+                RepositoryError::NotFound => invalid(),
+                _ => e.into(),
+            })?;
+
+        if token.revoked_at.is_some() || token.expires_at <= chrono::Utc::now().naive_utc() {
+            return Err(invalid());
+        }
+        if token.used_at.is_some() {
+            return Err(self.reject_reuse(token.family_id).await);
+        }
+
+        let user = match self.token_owner(token.user_id).await {
+            Ok(user) => user,
+            Err(RepositoryError::NotFound) => {
+                self.refresh_token_repository
+                    .revoke_family(token.family_id)
+                    .await?;
+                return Err(invalid());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.check_verified(&user)?;
+
+        let (refresh_token, successor) = self.new_refresh_token(&user, token.family_id);
+        let rotated = self
+            .refresh_token_repository
+            .rotate(token.id, successor)
+            .await?;
+        if rotated.is_none() {
+            // Lost a race against another request presenting the same token
+            return Err(self.reject_reuse(token.family_id).await);
+        }
+        self.access_token_response(&user, refresh_token)
+    }
+
+    /// Revokes the session the refresh token belongs to. Unknown tokens are
+    /// ignored so logging out twice is harmless.
+    pub async fn logout(&self, refresh_token: &str) -> Result<(), AuthError> {
+        match self
+            .refresh_token_repository
+            .find_by_hash(&hash_token(refresh_token))
+            .await
+        {
+            Ok(token) => {
+                self.refresh_token_repository
+                    .revoke_family(token.family_id)
+                    .await?;
+                Ok(())
+            }
+            Err(RepositoryError::NotFound) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Revokes every refresh token of the user, ending all their sessions.
+    pub async fn logout_all(&self, user_id: Uuid) -> Result<(), AuthError> {
+        self.refresh_token_repository.revoke_user(user_id).await?;
+        Ok(())
+    }
+
+    /// Sends a password reset link if the email belongs to an active user.
+    ///
+    /// Succeeds whether or not the account exists so the endpoint cannot be
+    /// used to discover registered emails; the handler runs it off the
+    /// request path so its timing gives nothing away either. Requesting a
+    /// new link invalidates the previous ones.
+    pub async fn forgot_password(&self, tenant: Tenant, email: &str) -> Result<(), AuthError> {
+        let user = match self.repository.get_user_by_email(tenant, email).await {
+            Ok(user) => user,
+            Err(RepositoryError::NotFound) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let token = generate_token();
+        let new_token = NewPasswordResetToken {
+            user_id: user.id,
+            token_hash: hash_token(&token),
+            expires_at: chrono::Utc::now().naive_utc()
+                + chrono::Duration::seconds(self.config.password_reset_ttl),
+        };
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            tx.password_resets().invalidate_user(user.id).await?;
+            tx.password_resets().create(new_token).await?;
+            Ok::<_, AuthError>(())
+        })
+        .await?;
+
+        let link = format!("{}?token={}", self.config.password_reset_url, token);
+        let notification = Notification {
+            to: user.email,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Hello {},\n\nUse the link below to choose a new password. It expires in {} minutes.\n\n{}\n\nIf you did not ask for this, you can ignore this message.",
+                user.name,
+                self.config.password_reset_ttl / 60,
+                link
+            ),
+        };
+        if let Err(e) = self.notifier.send(notification).await {
+            tracing::error!(user_id = %user.id, "Failed to send password reset link: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Sets a new password using a reset token. The token is single use, and
+    /// every session of the user is revoked.
+    pub async fn reset_password(
+        &self,
+        input: ResetPasswordRequest,
+        ctx: &AuditContext,
+    ) -> Result<(), AuthError> {
+        let invalid_token = || {
+            let mut errors = ValidationErrors::new();
+            errors.add("token", INVALID_RESET_TOKEN);
+            AuthError::ValidationError(errors)
+        };
+
+        // Hash the password before any row is locked. An invalid one is
+        // reported below, together with the token
+        let mut errors = ValidationErrors::new();
+        check_password(&mut errors, "password", &input.password);
+        let password = if errors.is_empty() {
+            hash_password(input.password, self.config.bcrypt_cost)
+                .await
+                .map_err(AuthError::HashError)?
+        } else {
+            String::new()
+        };
+
+        // Using up the token, setting the password and ending the sessions
+        // happen together or not at all
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let token = match tx
+                .password_resets()
+                .find_by_hash(&hash_token(&input.token))
+                .await
+            {
+                Ok(token)
+                    if token.used_at.is_none()
+                        && token.expires_at > chrono::Utc::now().naive_utc() =>
+                {
+                    Some(token)
+                }
+                Ok(_) | Err(RepositoryError::NotFound) => None,
+                Err(e) => return Err(e.into()),
+            };
+            let Some(token) = token else {
+                errors.add("token", INVALID_RESET_TOKEN);
+                return Err(AuthError::ValidationError(errors));
+            };
+            errors.into_result().map_err(AuthError::ValidationError)?;
+
+            let tenant = Tenant::from(&tx.organizations().find_by_user(token.user_id).await?);
+            let mut user = match tx
+                .users()
+                .lock_user(tenant, token.user_id, LockMode::Update)
+                .await
+            {
+                Ok(user) => user,
+                Err(RepositoryError::NotFound) => return Err(invalid_token()),
+                Err(e) => return Err(e.into()),
+            };
+            if !tx.password_resets().consume(token.id).await? {
+                return Err(invalid_token());
+            }
+
+            let before = user.clone();
+            user.password = password;
+            user.updated_at = chrono::Utc::now().naive_utc();
+            let user = tx
+                .users()
+                .update_user(tenant, user.id, user, before.updated_at)
+                .await?;
+            tx.audit()
+                .record(
+                    tenant,
+                    acting_user(ctx, user.id).event(AuditAction::Update, Some(&before), &user),
+                )
+                .await?;
+
+            tx.password_resets().invalidate_user(user.id).await?;
+            tx.refresh_tokens().revoke_user(user.id).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Confirms the email address a verification token was issued for. The
+    /// token is rejected once the user has switched to another address.
+    pub async fn verify_email(
+        &self,
+        input: VerifyEmailRequest,
+        ctx: &AuditContext,
+    ) -> Result<(), AuthError> {
+        let invalid = || {
+            let mut errors = ValidationErrors::new();
+            errors.add("token", "Invalid or expired verification token");
+            AuthError::ValidationError(errors)
+        };
+
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let token = match tx
+                .email_verifications()
+                .find_by_hash(&hash_token(&input.token))
+                .await
+            {
+                Ok(token) => token,
+                Err(RepositoryError::NotFound) => return Err(invalid()),
+                Err(e) => return Err(e.into()),
+            };
+            if token.used_at.is_some() || token.expires_at <= chrono::Utc::now().naive_utc() {
+                return Err(invalid());
+            }
+
+            let tenant = Tenant::from(&tx.organizations().find_by_user(token.user_id).await?);
+            let mut user = match tx
+                .users()
+                .lock_user(tenant, token.user_id, LockMode::Update)
+                .await
+            {
+                Ok(user) => user,
+                Err(RepositoryError::NotFound) => return Err(invalid()),
+                Err(e) => return Err(e.into()),
+            };
+            if user.email.to_lowercase() != token.email.to_lowercase() {
+                return Err(invalid());
+            }
+            if !tx.email_verifications().consume(token.id).await? {
+                return Err(invalid());
+            }
+
+            let before = user.clone();
+            let now = chrono::Utc::now().naive_utc();
+            user.email_verified_at = Some(now);
+            user.updated_at = now;
+            let user = tx
+                .users()
+                .update_user(tenant, user.id, user, before.updated_at)
+                .await?;
+            tx.audit()
+                .record(
+                    tenant,
+                    acting_user(ctx, user.id).event(AuditAction::Update, Some(&before), &user),
+                )
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Loads the live user a token was issued to, within their organization.
+    async fn token_owner(&self, user_id: Uuid) -> Result<User, RepositoryError> {
+        let organization = self.organization_repository.find_by_user(user_id).await?;
+        self.repository
+            .get_user(Tenant::from(&organization), user_id, false)
+            .await
+    }
+
+    /// Refuses users whose email is not verified, when that is required.
+    fn check_verified(&self, user: &User) -> Result<(), AuthError> {
+        if self.config.require_verified_email && user.email_verified_at.is_none() {
+            return Err(AuthError::EmailNotVerified(
+                "Email address has not been verified".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn reject_reuse(&self, family_id: Uuid) -> AuthError {
+        tracing::warn!(%family_id, "Refresh token reuse detected, revoking token family");
+        match self.refresh_token_repository.revoke_family(family_id).await {
+            Ok(_) => AuthError::InvalidToken("Refresh token has already been used".to_string()),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn issue_tokens(&self, user: &User, family_id: Uuid) -> Result<LoginResponse, AuthError> {
+        let (refresh_token, new_token) = self.new_refresh_token(user, family_id);
+        self.refresh_token_repository.create(new_token).await?;
+        self.access_token_response(user, refresh_token)
+    }
+
+    /// Generates a random refresh token, returning it together with the row
+    /// that stores its hash.
+    fn new_refresh_token(&self, user: &User, family_id: Uuid) -> (String, NewRefreshToken) {
+        let token = generate_token();
+        let expires_at = chrono::Utc::now().naive_utc()
+            + chrono::Duration::seconds(self.config.refresh_token_ttl);
+        let row = NewRefreshToken {
+            user_id: user.id,
+            family_id,
+            token_hash: hash_token(&token),
+            expires_at,
+        };
+        (token, row)
+    }
+
+    fn access_token_response(
+        &self,
+        user: &User,
+        refresh_token: String,
+    ) -> Result<LoginResponse, AuthError> {
+        let now = chrono::Utc::now().timestamp();
+        let claims = Claims {
+            sub: user.id,
+            org: user.organization_id,
+            iat: now,
+            exp: now + self.config.access_token_ttl,
+        };
+        let access_token = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.config.jwt_secret.as_bytes()),
+        )
+        .map_err(|e| AuthError::TokenError(format!("Failed to sign token: {}", e)))?;
+
+        Ok(LoginResponse {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expires_in: self.config.access_token_ttl,
+            refresh_token,
+            refresh_expires_in: self.config.refresh_token_ttl,
+        })
+    }
+
+    pub fn verify_token(&self, token: &str) -> Result<Claims, AuthError> {
+        decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(self.config.jwt_secret.as_bytes()),
+            &Validation::default(),
+        )
+        .map(|data| data.claims)
+        .map_err(|_| AuthError::InvalidToken("Invalid or expired token".to_string()))
+    }
+
+    /// Verifies the token and loads the caller's current roles, so role changes
+    /// and deleted accounts take effect without waiting for the token to expire.
+    pub async fn authenticate(&self, token: &str) -> Result<AuthUser, AuthError> {
+        let claims = self.verify_token(token)?;
+        let invalid = |e: RepositoryError| match e {
+            RepositoryError::NotFound => {
+                AuthError::InvalidToken("Invalid or expired token".to_string())
+            }
+            _ => e.into(),
+        };
+
+        let tenant = Tenant::new(claims.org);
+        let user = self
+            .repository
+            .get_user(tenant, claims.sub, false)
+            .await
+            .map_err(invalid)?;
+        let roles = self
+            .role_repository
+            .find_by_users(tenant, &[user.id])
+            .await?;
+        let mut role_codes = Vec::new();
+        for (_, role) in roles {
+            let ancestors = self
+                .role_repository
+                .find_ancestors(tenant, role.id, false)
+                .await?;
+            for role in std::iter::once(role).chain(ancestors) {
+                if !role_codes.contains(&role.code) {
+                    role_codes.push(role.code);
+                }
+            }
+        }
+
+        Ok(AuthUser {
+            id: user.id,
+            organization_id: user.organization_id,
+            role_codes,
+        })
+    }
+}
+
+/// The audit context of a change made through an emailed token, whose
+/// holder acts as the user it was issued to.
+fn acting_user(ctx: &AuditContext, user_id: Uuid) -> AuditContext {
+    AuditContext {
+        actor_id: Some(user_id),
+        ..ctx.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::audit::AuditEvent;
+    use crate::models::email_verification::NewEmailVerificationToken;
+    use crate::models::query::AuditListQuery;
+    use crate::models::role::{NewRole, ROLE_USER_MANAGER};
+    use crate::models::user::NewUser;
+    use crate::notifications::RecordingNotifier;
+    use crate::repositories::memory::InMemoryStore;
+    use crate::repositories::{AuditStore, EmailVerificationStore};
+
+    /// The only update recorded in the audit log of `tenant`.
+    async fn audited_update(store: &InMemoryStore, tenant: Tenant) -> AuditEvent {
+        let (mut events, total) = AuditStore::find_all(
+            store,
+            tenant,
+            &AuditListQuery {
+                action: Some("update".to_string()),
+                ..AuditListQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 1);
+        events.remove(0)
+    }
+
+    async fn setup() -> (AuthService, Tenant, InMemoryStore, RecordingNotifier) {
+        let store = InMemoryStore::new();
+        let tenant = store.create_organization("acme");
+        let role = RoleStore::create(
+            &store,
+            tenant,
+            NewRole {
+                name: "Admin".to_string(),
+                code: "ADMIN".to_string(),
+                description: String::new(),
+                parent_role_id: None,
+            },
+        )
+        .await
+        .unwrap();
+        let user = UserStore::create_user(
+            &store,
+            tenant,
+            NewUser {
+                name: "Admin".to_string(),
+                email: "admin@example.com".to_string(),
+                password: bcrypt::hash("secret123", 4).unwrap(),
+                role_ids: vec![role.id],
+            },
+        )
+        .await
+        .unwrap();
+        RoleStore::assign(&store, tenant, user.id, role.id)
+            .await
+            .unwrap();
+        let config = AuthConfig {
+            jwt_secret: "test-secret".to_string(),
+            access_token_ttl: 60,
+            refresh_token_ttl: 3600,
+            password_reset_ttl: 600,
+            password_reset_url: "https://app.example.com/reset".to_string(),
+            bcrypt_cost: 4,
+            ..AuthConfig::default()
+        };
+        let notifier = RecordingNotifier::default();
+        let service = AuthService::new(
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+            Arc::new(notifier.clone()),
+            config,
+        );
+        (service, tenant, store, notifier)
+    }
+
+    fn login(password: &str) -> LoginRequest {
+        LoginRequest {
+            email: "ADMIN@example.com".to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_issues_token_resolving_current_roles() {
+        let (service, tenant, store, _) = setup().await;
+
+        let response = service.login(tenant, login("secret123")).await.unwrap();
+        let user = service.authenticate(&response.access_token).await.unwrap();
+        assert_eq!(user.role_codes, vec!["ADMIN"]);
+
+        let support = RoleStore::create(
+            &store,
+            tenant,
+            NewRole {
+                name: "Support".to_string(),
+                code: "SUPPORT".to_string(),
+                description: String::new(),
+                parent_role_id: None,
+            },
+        )
+        .await
+        .unwrap();
+        RoleStore::assign(&store, tenant, user.id, support.id)
+            .await
+            .unwrap();
+        let user = service.authenticate(&response.access_token).await.unwrap();
+        assert_eq!(user.role_codes, vec!["ADMIN", "SUPPORT"]);
+        assert!(user.is_admin() && user.has_role("SUPPORT"));
+    }
+
+    #[tokio::test]
+    async fn authenticate_resolves_inherited_roles() {
+        let (service, tenant, store, _) = setup().await;
+        let response = service.login(tenant, login("secret123")).await.unwrap();
+        let user = service.authenticate(&response.access_token).await.unwrap();
+
+        let manager = RoleStore::create(
+            &store,
+            tenant,
+            NewRole {
+                name: "User manager".to_string(),
+                code: ROLE_USER_MANAGER.to_string(),
+                description: String::new(),
+                parent_role_id: None,
+            },
+        )
+        .await
+        .unwrap();
+        let support = RoleStore::create(
+            &store,
+            tenant,
+            NewRole {
+                name: "Support".to_string(),
+                code: "SUPPORT".to_string(),
+                description: String::new(),
+                parent_role_id: Some(manager.id),
+            },
+        )
+        .await
+        .unwrap();
+        RoleStore::assign(&store, tenant, user.id, support.id)
+            .await
+            .unwrap();
+
+        let user = service.authenticate(&response.access_token).await.unwrap();
+        assert_eq!(user.role_codes, vec!["ADMIN", "SUPPORT", ROLE_USER_MANAGER]);
+    }
+
+    #[tokio::test]
+    async fn login_is_scoped_to_the_organization() {
+        let (service, tenant, store, _) = setup().await;
+        let other = store.create_organization("globex");
+
+        let err = service.login(other, login("secret123")).await.unwrap_err();
+        assert!(matches!(err, AuthError::InvalidCredentials(_)));
+
+        let response = service.login(tenant, login("secret123")).await.unwrap();
+        let claims = service.verify_token(&response.access_token).unwrap();
+        assert_eq!(claims.org, tenant.id());
+        let user = service.authenticate(&response.access_token).await.unwrap();
+        assert_eq!(user.organization_id, tenant.id());
+    }
+
+    #[tokio::test]
+    async fn login_rejects_wrong_password_and_unknown_email_alike() {
+        let (service, tenant, _, _) = setup().await;
+
+        let wrong_password = service
+            .login(tenant, login("wrong-password"))
+            .await
+            .unwrap_err();
+        let unknown_email = service
+            .login(
+                tenant,
+                LoginRequest {
+                    email: "nobody@example.com".to_string(),
+                    password: "secret123".to_string(),
+                },
+            )
+            .await
+            .unwrap_err();
+
+        match (wrong_password, unknown_email) {
+            (AuthError::InvalidCredentials(a), AuthError::InvalidCredentials(b)) => {
+                assert_eq!(a, b)
+            }
+            other => panic!("expected invalid credentials, got {:?}", other),
+        }
+        assert!(bcrypt::verify("secret123", &service.dummy_hash).is_ok_and(|valid| !valid));
+    }
+
+    #[tokio::test]
+    async fn token_of_deleted_user_is_rejected() {
+        let (service, tenant, store, _) = setup().await;
+        let response = service.login(tenant, login("secret123")).await.unwrap();
+        let user = store
+            .get_user_by_email(tenant, "admin@example.com")
+            .await
+            .unwrap();
+        store
+            .delete_user(tenant, user.id, user.updated_at)
+            .await
+            .unwrap();
+
+        let err = service
+            .authenticate(&response.access_token)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, AuthError::InvalidToken(_)));
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_token() {
+        let (service, tenant, _, _) = setup().await;
+        let first = service.login(tenant, login("secret123")).await.unwrap();
+
+        let second = service.refresh(&first.refresh_token).await.unwrap();
+
+        assert_ne!(second.refresh_token, first.refresh_token);
+        assert!(service.authenticate(&second.access_token).await.is_ok());
+        assert!(service.refresh(&second.refresh_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn refresh_rechecks_the_token_owner() {
+        let (mut service, tenant, store, _) = setup().await;
+        let session = service.login(tenant, login("secret123")).await.unwrap();
+        service.config.require_verified_email = true;
+
+        let err = service.refresh(&session.refresh_token).await.unwrap_err();
+        assert!(matches!(err, AuthError::EmailNotVerified(_)));
+
+        let token = issue_verification(&store, tenant, "admin@example.com").await;
+        service
+            .verify_email(verify(&token), &AuditContext::default())
+            .await
+            .unwrap();
+        let user = store
+            .get_user_by_email(tenant, "admin@example.com")
+            .await
+            .unwrap();
+        store
+            .delete_user(tenant, user.id, user.updated_at)
+            .await
+            .unwrap();
+
+        let err = service.refresh(&session.refresh_token).await.unwrap_err();
+        assert!(matches!(err, AuthError::InvalidToken(_)));
+    }
+
+    #[tokio::test]
+    async fn reused_refresh_token_revokes_family() {
+        let (service, tenant, _, _) = setup().await;
+        let first = service.login(tenant, login("secret123")).await.unwrap();
+        let other_session = service.login(tenant, login("secret123")).await.unwrap();
+        let second = service.refresh(&first.refresh_token).await.unwrap();
+
+        let err = service.refresh(&first.refresh_token).await.unwrap_err();
+
+        assert!(matches!(err, AuthError::InvalidToken(_)));
+        assert!(service.refresh(&second.refresh_token).await.is_err());
+        assert!(service.refresh(&other_session.refresh_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn logout_revokes_only_that_session() {
+        let (service, tenant, _, _) = setup().await;
+        let first = service.login(tenant, login("secret123")).await.unwrap();
+        let other_session = service.login(tenant, login("secret123")).await.unwrap();
+
+        service.logout(&first.refresh_token).await.unwrap();
+        service.logout(&first.refresh_token).await.unwrap();
+
+        assert!(service.refresh(&first.refresh_token).await.is_err());
+        assert!(service.refresh(&other_session.refresh_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn logout_all_revokes_every_session() {
+        let (service, tenant, store, _) = setup().await;
+        let first = service.login(tenant, login("secret123")).await.unwrap();
+        let other_session = service.login(tenant, login("secret123")).await.unwrap();
+        let user = store
+            .get_user_by_email(tenant, "admin@example.com")
+            .await
+            .unwrap();
+
+        service.logout_all(user.id).await.unwrap();
+
+        assert!(service.refresh(&first.refresh_token).await.is_err());
+        assert!(service.refresh(&other_session.refresh_token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refresh_rejects_unknown_token() {
+        let (service, _, _, _) = setup().await;
+
+        let err = service.refresh("not-a-token").await.unwrap_err();
+
+        assert!(matches!(err, AuthError::InvalidToken(_)));
+    }
+
+    fn reset_token(notifier: &RecordingNotifier) -> String {
+        let sent = notifier.sent();
+        let body = &sent.last().expect("no notification sent").body;
+        let start = body.find("?token=").unwrap() + "?token=".len();
+        body[start..start + 64].to_string()
+    }
+
+    fn reset(token: &str, password: &str) -> ResetPasswordRequest {
+        ResetPasswordRequest {
+            token: token.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn forgot_password_ignores_unknown_email() {
+        let (service, tenant, _, notifier) = setup().await;
+
+        service
+            .forgot_password(tenant, "nobody@example.com")
+            .await
+            .unwrap();
+
+        assert!(notifier.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reset_password_sets_new_password_once() {
+        let (service, tenant, store, notifier) = setup().await;
+        let session = service.login(tenant, login("secret123")).await.unwrap();
+        service
+            .forgot_password(tenant, "admin@example.com")
+            .await
+            .unwrap();
+        let token = reset_token(&notifier);
+        assert_eq!(notifier.sent()[0].to, "admin@example.com");
+
+        service
+            .reset_password(reset(&token, "new-secret9"), &AuditContext::default())
+            .await
+            .unwrap();
+
+        assert!(service.login(tenant, login("new-secret9")).await.is_ok());
+        assert!(service.login(tenant, login("secret123")).await.is_err());
+        assert!(service.refresh(&session.refresh_token).await.is_err());
+        let event = audited_update(&store, tenant).await;
+        assert_eq!(
+            event.changes,
+            serde_json::json!({ "password": { "before": "[redacted]", "after": "[redacted]" } })
+        );
+        let err = service
+            .reset_password(reset(&token, "other-secret9"), &AuditContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AuthError::ValidationError(_)));
+    }
+
+    #[tokio::test]
+    async fn new_reset_link_invalidates_previous_one() {
+        let (service, tenant, _, notifier) = setup().await;
+        service
+            .forgot_password(tenant, "admin@example.com")
+            .await
+            .unwrap();
+        let first = reset_token(&notifier);
+        service
+            .forgot_password(tenant, "admin@example.com")
+            .await
+            .unwrap();
+        let second = reset_token(&notifier);
+
+        assert!(
+            service
+                .reset_password(reset(&first, "new-secret9"), &AuditContext::default())
+                .await
+                .is_err()
+        );
+        assert!(
+            service
+                .reset_password(reset(&second, "new-secret9"), &AuditContext::default())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn reset_password_reports_token_and_password_errors() {
+        let (service, _, _, _) = setup().await;
+
+        let err = service
+            .reset_password(reset("bogus", "short"), &AuditContext::default())
+            .await
+            .unwrap_err();
+
+        let AuthError::ValidationError(errors) = err else {
+            panic!("expected validation error, got {:?}", err);
+        };
+        let fields = serde_json::to_value(errors).unwrap();
+        assert!(fields.get("token").is_some());
+        assert!(fields.get("password").is_some());
+    }
+
+    async fn issue_verification(store: &InMemoryStore, tenant: Tenant, email: &str) -> String {
+        let user = store
+            .get_user_by_email(tenant, "admin@example.com")
+            .await
+            .unwrap();
+        let token = generate_token();
+        EmailVerificationStore::create(
+            store,
+            NewEmailVerificationToken {
+                user_id: user.id,
+                email: email.to_string(),
+                token_hash: hash_token(&token),
+                expires_at: chrono::Utc::now().naive_utc() + chrono::Duration::hours(1),
+            },
+        )
+        .await
+        .unwrap();
+        token
+    }
+
+    fn verify(token: &str) -> VerifyEmailRequest {
+        VerifyEmailRequest {
+            token: token.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_email_marks_address_verified_once() {
+        let (service, tenant, store, _) = setup().await;
+        let token = issue_verification(&store, tenant, "admin@example.com").await;
+
+        service
+            .verify_email(verify(&token), &AuditContext::default())
+            .await
+            .unwrap();
+
+        let user = store
+            .get_user_by_email(tenant, "admin@example.com")
+            .await
+            .unwrap();
+        assert!(user.email_verified_at.is_some());
+        let event = audited_update(&store, tenant).await;
+        assert_eq!(event.actor_id, Some(user.id));
+        assert!(event.changes.get("email_verified_at").is_some());
+        assert!(
+            service
+                .verify_email(verify(&token), &AuditContext::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_email_rejects_token_for_previous_address() {
+        let (service, tenant, store, _) = setup().await;
+        let token = issue_verification(&store, tenant, "old@example.com").await;
+
+        let err = service
+            .verify_email(verify(&token), &AuditContext::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, AuthError::ValidationError(_)));
+    }
+
+    #[tokio::test]
+    async fn unverified_users_cannot_log_in_when_required() {
+        let (mut service, tenant, store, _) = setup().await;
+        service.config.require_verified_email = true;
+
+        let err = service.login(tenant, login("secret123")).await.unwrap_err();
+        assert!(matches!(err, AuthError::EmailNotVerified(_)));
+
+        let token = issue_verification(&store, tenant, "admin@example.com").await;
+        service
+            .verify_email(verify(&token), &AuditContext::default())
+            .await
+            .unwrap();
+        assert!(service.login(tenant, login("secret123")).await.is_ok());
+    }
+}
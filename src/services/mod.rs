@@ -1,2 +1,6 @@
+pub mod audit_services;
+pub mod auth_services;
+pub mod passwords;
 pub mod role_services;
+pub mod tokens;
 pub mod user_services;
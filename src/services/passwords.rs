@@ -0,0 +1,18 @@
+//! Password hashing. bcrypt is deliberately slow, so both operations run on
+//! the blocking pool instead of holding up an async worker.
+
+/// Hashes `password` at the given bcrypt cost.
+pub async fn hash_password(password: String, cost: u32) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || bcrypt::hash(password, cost))
+        .await
+        .map_err(|e| format!("Password task failed: {}", e))?
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+/// Checks `password` against a stored bcrypt hash.
+pub async fn verify_password(password: String, hash: String) -> Result<bool, String> {
+    tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash))
+        .await
+        .map_err(|e| format!("Password task failed: {}", e))?
+        .map_err(|e| format!("Failed to verify password: {}", e))
+}
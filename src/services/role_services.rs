@@ -1,84 +1,1507 @@
-use crate::models::role::{NewRole, Role};
-use crate::repositories::role_repository::RoleRepository;
-use diesel::result::Error as DieselError;
+use crate::models::audit::{AuditAction, AuditContext, Audited};
+use crate::models::etag::IfMatch;
+use crate::models::organization::Tenant;
+use crate::models::permission::{NewPermission, Permission};
+use crate::models::query::{RoleDeleteQuery, RoleListQuery};
+use crate::models::role::{NewRole, Role, RolePatch};
+use crate::models::user::User;
+use crate::repositories::{
+    LockMode, PermissionStore, RepositoryError, RoleStore, Transaction, UnitOfWork, UserStore,
+    in_transaction,
+};
+use crate::validation::{Validate, ValidationErrors};
+use serde_json::{Value, json};
+use std::sync::Arc;
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum RoleError {
     DatabaseError(String),
     NotFound(String),
+    Conflict {
+        field: String,
+        message: String,
+    },
+    ValidationError(ValidationErrors),
+    /// The role to delete is still assigned to `assigned_users` users.
+    InUse {
+        message: String,
+        assigned_users: usize,
+    },
+    /// The `If-Match` precondition failed or the role changed concurrently.
+    PreconditionFailed(String),
+    Unavailable(String),
 }
 
-impl From<DieselError> for RoleError {
-    fn from(err: DieselError) -> RoleError {
+const STALE_ROLE: &str = "Role has been modified since it was read";
+
+impl From<RepositoryError> for RoleError {
+    fn from(err: RepositoryError) -> RoleError {
         match err {
-            DieselError::NotFound => RoleError::NotFound("Role not found".to_string()),
-            _ => RoleError::DatabaseError(format!("Database error: {}", err)),
+            RepositoryError::NotFound => RoleError::NotFound("Role not found".to_string()),
+            RepositoryError::UniqueViolation(field) => RoleError::Conflict {
+                message: format!("A role with this {} already exists", field),
+                field,
+            },
+            RepositoryError::Stale => RoleError::PreconditionFailed(STALE_ROLE.to_string()),
+            RepositoryError::Unavailable(msg) => {
+                RoleError::Unavailable(format!("Database is unavailable: {}", msg))
+            }
+            RepositoryError::Database(msg) => {
+                RoleError::DatabaseError(format!("Database error: {}", msg))
+            }
         }
     }
 }
 
 pub struct RoleService {
-    pub repository: RoleRepository,
+    pub repository: Arc<dyn RoleStore>,
+    pub permission_repository: Arc<dyn PermissionStore>,
+    pub user_repository: Arc<dyn UserStore>,
+    pub unit_of_work: Arc<dyn UnitOfWork>,
 }
 
 impl RoleService {
-    pub fn new(repository: RoleRepository) -> Self {
-        Self { repository }
+    pub fn new(
+        repository: Arc<dyn RoleStore>,
+        permission_repository: Arc<dyn PermissionStore>,
+        user_repository: Arc<dyn UserStore>,
+        unit_of_work: Arc<dyn UnitOfWork>,
+    ) -> Self {
+        Self {
+            repository,
+            permission_repository,
+            user_repository,
+            unit_of_work,
+        }
+    }
+
+    pub async fn create_role(
+        &self,
+        tenant: Tenant,
+        role: NewRole,
+        ctx: &AuditContext,
+    ) -> Result<Role, RoleError> {
+        role.validate().map_err(RoleError::ValidationError)?;
+
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            check_parent(&*tx, tenant, None, role.parent_role_id).await?;
+            let role = tx.roles().create(tenant, role).await.map_err(|e| match e {
+                RepositoryError::Database(_) => {
+                    RoleError::DatabaseError("Failed to create role".to_string())
+                }
+                _ => e.into(),
+            })?;
+            tx.audit()
+                .record(tenant, ctx.event(AuditAction::Create, None, &role))
+                .await?;
+            Ok(role)
+        })
+        .await
+    }
+
+    pub async fn get_role(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        include_deleted: bool,
+    ) -> Result<Role, RoleError> {
+        self.repository
+            .find_by_id(tenant, id, include_deleted)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::NotFound => {
+                    RoleError::NotFound(format!("Role with id {} not found", id))
+                }
+                RepositoryError::Database(_) => {
+                    RoleError::DatabaseError("Failed to fetch role".to_string())
+                }
+                _ => e.into(),
+            })
+    }
+
+    pub async fn get_roles(
+        &self,
+        tenant: Tenant,
+        filter: &RoleListQuery,
+    ) -> Result<(Vec<Role>, i64), RoleError> {
+        self.repository
+            .find_all(tenant, filter)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::Database(_) => {
+                    RoleError::DatabaseError("Failed to fetch roles".to_string())
+                }
+                _ => e.into(),
+            })
+    }
+
+    /// Replaces every writable field of the role, as `PUT` does.
+    pub async fn update_role(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        input: NewRole,
+        if_match: &IfMatch,
+        ctx: &AuditContext,
+    ) -> Result<Role, RoleError> {
+        input.validate().map_err(RoleError::ValidationError)?;
+
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, if_match).await?;
+            let mut role = before.clone();
+            role.name = input.name;
+            role.code = input.code;
+            role.description = input.description;
+            role.parent_role_id = input.parent_role_id;
+
+            save(&*tx, tenant, &before, role, ctx).await
+        })
+        .await
+    }
+
+    /// Changes only the fields present in `input`, as `PATCH` does.
+    pub async fn patch_role(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        input: RolePatch,
+        if_match: &IfMatch,
+        ctx: &AuditContext,
+    ) -> Result<Role, RoleError> {
+        input.validate().map_err(RoleError::ValidationError)?;
+
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, if_match).await?;
+            let mut role = before.clone();
+            if let Some(name) = input.name {
+                role.name = name;
+            }
+            if let Some(code) = input.code {
+                role.code = code;
+            }
+            if let Some(description) = input.description {
+                role.description = description;
+            }
+            if let Some(parent_role_id) = input.parent_role_id {
+                role.parent_role_id = parent_role_id;
+            }
+
+            save(&*tx, tenant, &before, role, ctx).await
+        })
+        .await
+    }
+
+    /// The roles `id` inherits from, nearest first.
+    pub async fn get_role_ancestors(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+    ) -> Result<Vec<Role>, RoleError> {
+        self.get_role(tenant, id, false).await?;
+
+        self.repository
+            .find_ancestors(tenant, id, false)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::Database(_) => {
+                    RoleError::DatabaseError(format!("Failed to fetch ancestors of role {}", id))
+                }
+                _ => e.into(),
+            })
+    }
+
+    /// Soft deletes the role. While live users are assigned to it, `options`
+    /// has to either name a role to reassign them to or force the deletion,
+    /// which suspends their assignments until the role is restored.
+    pub async fn delete_role(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        options: &RoleDeleteQuery,
+        if_match: &IfMatch,
+        ctx: &AuditContext,
+    ) -> Result<Role, RoleError> {
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, if_match).await?;
+            check_delete_options(&*tx, tenant, id, options).await?;
+            let user_ids = tx.roles().find_user_ids(tenant, id).await?;
+            if !user_ids.is_empty() {
+                match options.reassign_to {
+                    Some(target) => {
+                        reassign_users(&*tx, tenant, id, Some(target), &user_ids, ctx).await?;
+                    }
+                    None if options.force => {
+                        reassign_users(&*tx, tenant, id, None, &user_ids, ctx).await?;
+                    }
+                    None => {
+                        return Err(RoleError::InUse {
+                            message: format!(
+                                "Role with id {} is still assigned to {} user(s); reassign them \
+                                 with reassign_to, or force the deletion to suspend their \
+                                 assignments until the role is restored",
+                                id,
+                                user_ids.len()
+                            ),
+                            assigned_users: user_ids.len(),
+                        });
+                    }
+                }
+            }
+
+            let role = tx
+                .roles()
+                .delete(tenant, id, before.updated_at)
+                .await
+                .map_err(|e| match e {
+                    RepositoryError::NotFound => {
+                        RoleError::NotFound(format!("Role with id {} not found", id))
+                    }
+                    RepositoryError::Database(_) => {
+                        RoleError::DatabaseError(format!("Failed to delete role with id {}", id))
+                    }
+                    _ => e.into(),
+                })?;
+            tx.audit()
+                .record(tenant, ctx.event(AuditAction::Delete, Some(&before), &role))
+                .await?;
+            Ok(role)
+        })
+        .await
     }
 
-    pub fn create_role(&self, role: NewRole) -> Result<Role, RoleError> {
-        self.repository.create(role).map_err(|e| match e {
-            DieselError::DatabaseError(_, _) => {
-                RoleError::DatabaseError("Failed to create role".to_string())
+    /// Restores a soft-deleted role, handing it back to the users it was
+    /// taken from by a forced deletion.
+    pub async fn restore_role(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        ctx: &AuditContext,
+    ) -> Result<Role, RoleError> {
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = tx.roles().find_by_id(tenant, id, true).await;
+            let role = tx.roles().restore(tenant, id).await.map_err(|e| match e {
+                RepositoryError::NotFound => {
+                    RoleError::NotFound(format!("Deleted role with id {} not found", id))
+                }
+                RepositoryError::Database(_) => {
+                    RoleError::DatabaseError(format!("Failed to restore role with id {}", id))
+                }
+                _ => e.into(),
+            })?;
+            tx.audit()
+                .record(
+                    tenant,
+                    ctx.event(AuditAction::Restore, before.ok().as_ref(), &role),
+                )
+                .await?;
+            for user_id in tx.roles().find_suspended_user_ids(tenant, id).await? {
+                change_user_roles(&*tx, tenant, user_id, ctx, async {
+                    tx.roles().resume(tenant, user_id, id).await?;
+                    Ok(())
+                })
+                .await?;
+            }
+            Ok(role)
+        })
+        .await
+    }
+
+    pub async fn get_permissions(&self, tenant: Tenant) -> Result<Vec<Permission>, RoleError> {
+        self.permission_repository
+            .find_all(tenant)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::Database(_) => {
+                    RoleError::DatabaseError("Failed to fetch permissions".to_string())
+                }
+                _ => e.into(),
+            })
+    }
+
+    pub async fn create_permission(
+        &self,
+        tenant: Tenant,
+        permission: NewPermission,
+        ctx: &AuditContext,
+    ) -> Result<Permission, RoleError> {
+        permission.validate().map_err(RoleError::ValidationError)?;
+
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let permission =
+                tx.permissions()
+                    .create(tenant, permission)
+                    .await
+                    .map_err(|e| match e {
+                        RepositoryError::UniqueViolation(field) => RoleError::Conflict {
+                            message: format!("A permission with this {} already exists", field),
+                            field,
+                        },
+                        RepositoryError::Database(_) => {
+                            RoleError::DatabaseError("Failed to create permission".to_string())
+                        }
+                        _ => e.into(),
+                    })?;
+            tx.audit()
+                .record(tenant, ctx.event(AuditAction::Create, None, &permission))
+                .await?;
+            Ok(permission)
+        })
+        .await
+    }
+
+    pub async fn get_role_permissions(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+    ) -> Result<Vec<Permission>, RoleError> {
+        self.get_role(tenant, id, false).await?;
+
+        self.permission_repository
+            .find_by_role(tenant, id)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::Database(_) => {
+                    RoleError::DatabaseError(format!("Failed to fetch permissions of role {}", id))
+                }
+                _ => e.into(),
+            })
+    }
+
+    pub async fn assign_permission(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        permission_id: Uuid,
+        ctx: &AuditContext,
+    ) -> Result<Vec<Permission>, RoleError> {
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            lock_role(&*tx, tenant, id).await?;
+            tx.permissions()
+                .find_by_id(tenant, permission_id)
+                .await
+                .map_err(|e| match e {
+                    RepositoryError::NotFound => RoleError::NotFound(format!(
+                        "Permission with id {} not found",
+                        permission_id
+                    )),
+                    RepositoryError::Database(_) => {
+                        RoleError::DatabaseError("Failed to fetch permission".to_string())
+                    }
+                    _ => e.into(),
+                })?;
+
+            let before = permission_codes(&*tx, tenant, id).await?;
+            let assigned = tx
+                .permissions()
+                .assign(tenant, id, permission_id)
+                .await
+                .map_err(|e| match e {
+                    RepositoryError::Database(_) => RoleError::DatabaseError(format!(
+                        "Failed to assign permission to role {}",
+                        id
+                    )),
+                    _ => e.into(),
+                })?;
+            if assigned > 0 {
+                let after = permission_codes(&*tx, tenant, id).await?;
+                tx.audit()
+                    .record(
+                        tenant,
+                        ctx.event_with(AuditAction::Update, Role::ENTITY_TYPE, id, &before, &after),
+                    )
+                    .await?;
+            }
+            role_permissions(&*tx, tenant, id).await
+        })
+        .await
+    }
+
+    pub async fn revoke_permission(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        permission_id: Uuid,
+        ctx: &AuditContext,
+    ) -> Result<Vec<Permission>, RoleError> {
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            lock_role(&*tx, tenant, id).await?;
+            let before = permission_codes(&*tx, tenant, id).await?;
+            let removed = tx
+                .permissions()
+                .unassign(tenant, id, permission_id)
+                .await
+                .map_err(|e| match e {
+                    RepositoryError::Database(_) => RoleError::DatabaseError(format!(
+                        "Failed to revoke permission from role {}",
+                        id
+                    )),
+                    _ => e.into(),
+                })?;
+            if removed == 0 {
+                return Err(RoleError::NotFound(format!(
+                    "Permission with id {} is not assigned to role {}",
+                    permission_id, id
+                )));
+            }
+            let after = permission_codes(&*tx, tenant, id).await?;
+            tx.audit()
+                .record(
+                    tenant,
+                    ctx.event_with(AuditAction::Update, Role::ENTITY_TYPE, id, &before, &after),
+                )
+                .await?;
+            role_permissions(&*tx, tenant, id).await
+        })
+        .await
+    }
+
+    /// Resolves the effective permission codes of a user through their roles
+    /// and the roles those inherit from.
+    pub async fn get_user_permissions(
+        &self,
+        tenant: Tenant,
+        user_id: Uuid,
+    ) -> Result<Vec<String>, RoleError> {
+        self.user_repository
+            .get_user(tenant, user_id, false)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::NotFound => {
+                    RoleError::NotFound(format!("User with id {} not found", user_id))
+                }
+                RepositoryError::Database(_) => {
+                    RoleError::DatabaseError("Failed to fetch user".to_string())
+                }
+                _ => e.into(),
+            })?;
+
+        self.permission_repository
+            .find_codes_by_user(tenant, user_id)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::Database(_) => RoleError::DatabaseError(format!(
+                    "Failed to resolve permissions of user {}",
+                    user_id
+                )),
+                _ => e.into(),
+            })
+    }
+}
+
+/// Fetches and locks the role a write is about to change, checking that the
+/// caller's `If-Match` names its current version.
+async fn lock_current(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+    if_match: &IfMatch,
+) -> Result<Role, RoleError> {
+    let role = tx
+        .roles()
+        .lock(tenant, id, LockMode::Update)
+        .await
+        .map_err(|e| match e {
+            RepositoryError::NotFound => {
+                RoleError::NotFound(format!("Role with id {} not found", id))
+            }
+            RepositoryError::Database(_) => {
+                RoleError::DatabaseError("Failed to fetch role".to_string())
+            }
+            _ => e.into(),
+        })?;
+    if !if_match.matches(role.updated_at) {
+        return Err(RoleError::PreconditionFailed(STALE_ROLE.to_string()));
+    }
+    Ok(role)
+}
+
+/// Locks a live role whose permissions are about to change, so it cannot be
+/// deleted meanwhile.
+async fn lock_role(tx: &dyn Transaction, tenant: Tenant, id: Uuid) -> Result<Role, RoleError> {
+    tx.roles()
+        .lock(tenant, id, LockMode::Share)
+        .await
+        .map_err(|e| match e {
+            RepositoryError::NotFound => {
+                RoleError::NotFound(format!("Role with id {} not found", id))
+            }
+            RepositoryError::Database(_) => {
+                RoleError::DatabaseError("Failed to fetch role".to_string())
             }
             _ => e.into(),
         })
+}
+
+/// Checks that a role may inherit from `parent_role_id`: it has to be a live
+/// role of `tenant` that does not already inherit from `role_id`. The parent
+/// is locked so it stays that way until the write commits.
+async fn check_parent(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    role_id: Option<Uuid>,
+    parent_role_id: Option<Uuid>,
+) -> Result<(), RoleError> {
+    let Some(parent_id) = parent_role_id else {
+        return Ok(());
+    };
+    let mut errors = ValidationErrors::new();
+    match tx.roles().lock(tenant, parent_id, LockMode::Share).await {
+        Ok(_) => {
+            if let Some(role_id) = role_id {
+                // Deleted roles count too, so restoring one cannot close a cycle
+                let ancestors = tx.roles().find_ancestors(tenant, parent_id, true).await?;
+                if parent_id == role_id || ancestors.iter().any(|role| role.id == role_id) {
+                    errors.add(
+                        "parent_role_id",
+                        "A role cannot inherit from itself or from a role inheriting from it",
+                    );
+                }
+            }
+        }
+        Err(RepositoryError::NotFound) => {
+            errors.add(
+                "parent_role_id",
+                format!("Role with id {} not found", parent_id),
+            );
+        }
+        Err(e) => return Err(e.into()),
+    }
+    errors.into_result().map_err(RoleError::ValidationError)
+}
+
+/// Checks the options of deleting role `id`. A role to reassign the users
+/// to has to be a live role of `tenant` other than `id`, and is locked so it
+/// stays live until the deletion commits.
+async fn check_delete_options(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+    options: &RoleDeleteQuery,
+) -> Result<(), RoleError> {
+    let Some(target) = options.reassign_to else {
+        return Ok(());
+    };
+    let mut errors = ValidationErrors::new();
+    if options.force {
+        errors.add("force", "force cannot be combined with reassign_to");
     }
+    if target == id {
+        errors.add(
+            "reassign_to",
+            "Users cannot be reassigned to the role being deleted",
+        );
+    } else {
+        match tx.roles().lock(tenant, target, LockMode::Share).await {
+            Ok(_) => {}
+            Err(RepositoryError::NotFound) => {
+                errors.add("reassign_to", format!("Role with id {} not found", target));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    errors.into_result().map_err(RoleError::ValidationError)
+}
 
-    pub fn get_role(&self, id: Uuid) -> Result<Role, RoleError> {
-        self.repository.find_by_id(id).map_err(|e| match e {
-            DieselError::NotFound => RoleError::NotFound(format!("Role with id {} not found", id)),
-            _ => RoleError::DatabaseError("Failed to fetch role".to_string()),
+/// Replaces role `id` with `target` in the roles of each of `user_ids`, or
+/// suspends their assignment of it without a `target`.
+async fn reassign_users(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+    target: Option<Uuid>,
+    user_ids: &[Uuid],
+    ctx: &AuditContext,
+) -> Result<(), RoleError> {
+    for user_id in user_ids {
+        change_user_roles(tx, tenant, *user_id, ctx, async {
+            match target {
+                Some(target) => {
+                    tx.roles().assign(tenant, *user_id, target).await?;
+                    tx.roles().unassign(tenant, *user_id, id).await?;
+                }
+                None => {
+                    tx.roles().suspend(tenant, *user_id, id).await?;
+                }
+            }
+            Ok(())
         })
+        .await?;
     }
+    Ok(())
+}
 
-    pub fn get_roles(&self) -> Result<Vec<Role>, RoleError> {
-        self.repository
-            .find_all()
-            .map_err(|_e| RoleError::DatabaseError("Failed to fetch roles".to_string()))
+/// Applies `change` to the roles of a user, auditing it and bumping the
+/// user's `updated_at` as an assignment through the user endpoints does.
+async fn change_user_roles(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    user_id: Uuid,
+    ctx: &AuditContext,
+    change: impl Future<Output = Result<(), RoleError>>,
+) -> Result<(), RoleError> {
+    let mut user = tx
+        .users()
+        .lock_user(tenant, user_id, LockMode::Update)
+        .await?;
+    let before = assigned_role_ids(tx, tenant, user_id).await?;
+    change.await?;
+    let after = assigned_role_ids(tx, tenant, user_id).await?;
+    tx.audit()
+        .record(
+            tenant,
+            ctx.event_with(
+                AuditAction::Update,
+                User::ENTITY_TYPE,
+                user_id,
+                &json!({ "role_ids": before }),
+                &json!({ "role_ids": after }),
+            ),
+        )
+        .await?;
+
+    let expected = user.updated_at;
+    user.updated_at = chrono::Utc::now().naive_utc();
+    tx.users()
+        .update_user(tenant, user_id, user, expected)
+        .await?;
+    Ok(())
+}
+
+/// The ids of the live roles assigned to a user.
+async fn assigned_role_ids(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    user_id: Uuid,
+) -> Result<Vec<Uuid>, RoleError> {
+    let rows = tx.roles().find_by_users(tenant, &[user_id]).await?;
+    Ok(rows.into_iter().map(|(_, role)| role.id).collect())
+}
+
+/// Writes the modified role back and audits the change from `before`.
+async fn save(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    before: &Role,
+    mut role: Role,
+    ctx: &AuditContext,
+) -> Result<Role, RoleError> {
+    let id = before.id;
+    if role.parent_role_id != before.parent_role_id {
+        check_parent(tx, tenant, Some(id), role.parent_role_id).await?;
+    }
+    role.updated_at = chrono::Utc::now().naive_utc();
+    let role = tx
+        .roles()
+        .update(tenant, id, role, before.updated_at)
+        .await
+        .map_err(|e| match e {
+            RepositoryError::NotFound => {
+                RoleError::NotFound(format!("Role with id {} not found", id))
+            }
+            RepositoryError::Database(_) => {
+                RoleError::DatabaseError(format!("Failed to update role with id {}", id))
+            }
+            _ => e.into(),
+        })?;
+    tx.audit()
+        .record(tenant, ctx.event(AuditAction::Update, Some(before), &role))
+        .await?;
+    Ok(role)
+}
+
+/// The permissions assigned to a role, as returned after changing them.
+async fn role_permissions(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+) -> Result<Vec<Permission>, RoleError> {
+    tx.permissions()
+        .find_by_role(tenant, id)
+        .await
+        .map_err(|e| match e {
+            RepositoryError::Database(_) => {
+                RoleError::DatabaseError(format!("Failed to fetch permissions of role {}", id))
+            }
+            _ => e.into(),
+        })
+}
+
+/// The codes of the permissions assigned to a role, as the snapshot audited
+/// when the assignment changes.
+async fn permission_codes(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    role_id: Uuid,
+) -> Result<Value, RoleError> {
+    let codes: Vec<String> = tx
+        .permissions()
+        .find_by_role(tenant, role_id)
+        .await?
+        .into_iter()
+        .map(|permission| permission.code)
+        .collect();
+    Ok(json!({ "permissions": codes }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::etag::etag;
+    use crate::models::query::AuditListQuery;
+    use crate::models::user::NewUser;
+    use crate::repositories::AuditStore;
+    use crate::repositories::memory::InMemoryStore;
+
+    fn setup() -> (RoleService, Tenant, InMemoryStore) {
+        let store = InMemoryStore::new();
+        let tenant = store.create_organization("acme");
+        let service = RoleService::new(
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+        );
+        (service, tenant, store)
     }
 
-    pub fn update_role(&self, id: Uuid, input: NewRole) -> Result<Role, RoleError> {
-        let mut role_exist: Role = self
-            .repository
-            .find_by_id(id)
-            .map_err(|_| RoleError::NotFound(format!("Role with id {} not found", id)))?;
+    fn new_role(code: &str) -> NewRole {
+        NewRole {
+            name: format!("{} role", code),
+            code: code.to_string(),
+            description: String::new(),
+            parent_role_id: None,
+        }
+    }
 
-        // Update the role fields
-        if input.code != role_exist.code {
-            role_exist.code = input.code;
+    fn field_errors(err: RoleError) -> Vec<String> {
+        match err {
+            RoleError::ValidationError(errors) => serde_json::to_value(errors)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect(),
+            other => panic!("expected validation error, got {:?}", other),
         }
-        if input.description != role_exist.description {
-            role_exist.description = input.description;
+    }
+
+    async fn create_permission(service: &RoleService, tenant: Tenant, code: &str) -> Permission {
+        service
+            .create_permission(
+                tenant,
+                NewPermission {
+                    name: code.to_string(),
+                    code: code.to_string(),
+                    description: String::new(),
+                },
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn create_role_rejects_invalid_code() {
+        let (service, tenant, _) = setup();
+
+        let err = service
+            .create_role(tenant, new_role("NOT VALID"), &AuditContext::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, RoleError::ValidationError(_)));
+    }
+
+    #[tokio::test]
+    async fn create_role_rejects_duplicate_code_case_insensitively() {
+        let (service, tenant, _) = setup();
+        service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+
+        let err = service
+            .create_role(tenant, new_role("editor"), &AuditContext::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, RoleError::Conflict { field, .. } if field == "code"));
+    }
+
+    #[tokio::test]
+    async fn roles_are_scoped_to_their_organization() {
+        let (service, tenant, store) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+        let permission = create_permission(&service, tenant, "users.read").await;
+        let other = store.create_organization("globex");
+
+        assert!(matches!(
+            service.get_role(other, role.id, true).await,
+            Err(RoleError::NotFound(_))
+        ));
+        assert!(matches!(
+            service
+                .assign_permission(other, role.id, permission.id, &AuditContext::default())
+                .await,
+            Err(RoleError::NotFound(_))
+        ));
+        let (roles, _) = service
+            .get_roles(other, &RoleListQuery::default())
+            .await
+            .unwrap();
+        assert!(roles.is_empty());
+
+        let copy = service
+            .create_role(other, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+        assert_ne!(copy.id, role.id);
+    }
+
+    #[tokio::test]
+    async fn permissions_are_scoped_to_their_organization() {
+        let (service, tenant, store) = setup();
+        let permission = create_permission(&service, tenant, "users.read").await;
+        let other = store.create_organization("globex");
+        let role = service
+            .create_role(other, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+
+        assert!(service.get_permissions(other).await.unwrap().is_empty());
+        assert!(matches!(
+            service
+                .assign_permission(other, role.id, permission.id, &AuditContext::default())
+                .await,
+            Err(RoleError::NotFound(_))
+        ));
+
+        let copy = create_permission(&service, other, "USERS.READ").await;
+        assert_ne!(copy.id, permission.id);
+        let err = service
+            .create_permission(
+                other,
+                NewPermission {
+                    name: "users.read".to_string(),
+                    code: "users.read".to_string(),
+                    description: String::new(),
+                },
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RoleError::Conflict { field, .. } if field == "code"));
+
+        let codes: Vec<String> = service
+            .get_permissions(tenant)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|p| p.code)
+            .collect();
+        assert_eq!(codes, vec!["users.read"]);
+    }
+
+    #[tokio::test]
+    async fn code_of_deleted_role_can_be_reused() {
+        let (service, tenant, _) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+        service
+            .delete_role(
+                tenant,
+                role.id,
+                &RoleDeleteQuery::default(),
+                &IfMatch::Any,
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service
+                .restore_role(tenant, role.id, &AuditContext::default())
+                .await,
+            Err(RoleError::Conflict { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn patch_role_changes_only_given_fields() {
+        let (service, tenant, _) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+
+        let patched = service
+            .patch_role(
+                tenant,
+                role.id,
+                RolePatch {
+                    description: Some("Edits content".to_string()),
+                    ..RolePatch::default()
+                },
+                &IfMatch::Any,
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(patched.name, role.name);
+        assert_eq!(patched.code, role.code);
+        assert_eq!(patched.description, "Edits content");
+    }
+
+    #[tokio::test]
+    async fn patch_role_validates_given_fields() {
+        let (service, tenant, _) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+
+        let err = service
+            .patch_role(
+                tenant,
+                role.id,
+                RolePatch {
+                    code: Some("NOT VALID".to_string()),
+                    ..RolePatch::default()
+                },
+                &IfMatch::Any,
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, RoleError::ValidationError(_)));
+    }
+
+    #[tokio::test]
+    async fn update_role_rejects_stale_etag() {
+        let (service, tenant, _) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+        let stale = IfMatch::parse(&etag(role.updated_at));
+        service
+            .update_role(
+                tenant,
+                role.id,
+                new_role("AUTHOR"),
+                &stale,
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        let err = service
+            .update_role(
+                tenant,
+                role.id,
+                new_role("WRITER"),
+                &stale,
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, RoleError::PreconditionFailed(_)));
+        let current = service.get_role(tenant, role.id, false).await.unwrap();
+        assert_eq!(current.code, "AUTHOR");
+    }
+
+    #[tokio::test]
+    async fn assign_and_revoke_permissions() {
+        let (service, tenant, _) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+        let permission = create_permission(&service, tenant, "users.read").await;
+
+        let assigned = service
+            .assign_permission(tenant, role.id, permission.id, &AuditContext::default())
+            .await
+            .unwrap();
+        assert_eq!(assigned.len(), 1);
+
+        let again = service
+            .assign_permission(tenant, role.id, permission.id, &AuditContext::default())
+            .await
+            .unwrap();
+        assert_eq!(again.len(), 1);
+
+        let revoked = service
+            .revoke_permission(tenant, role.id, permission.id, &AuditContext::default())
+            .await
+            .unwrap();
+        assert!(revoked.is_empty());
+
+        assert!(matches!(
+            service
+                .revoke_permission(tenant, role.id, permission.id, &AuditContext::default())
+                .await,
+            Err(RoleError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn permission_changes_are_audited_on_the_role() {
+        let (service, tenant, store) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+        let permission = create_permission(&service, tenant, "users.read").await;
+        for _ in 0..2 {
+            service
+                .assign_permission(tenant, role.id, permission.id, &AuditContext::default())
+                .await
+                .unwrap();
+        }
+
+        let (events, total) = AuditStore::find_all(
+            &store,
+            tenant,
+            &AuditListQuery {
+                entity_id: Some(role.id),
+                action: Some("update".to_string()),
+                ..AuditListQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(total, 1);
+        assert_eq!(
+            events[0].changes,
+            json!({ "permissions": { "before": [], "after": ["users.read"] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn assign_rejects_unknown_permission() {
+        let (service, tenant, _) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+
+        let err = service
+            .assign_permission(tenant, role.id, Uuid::new_v4(), &AuditContext::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, RoleError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn user_permissions_are_the_union_of_their_roles() {
+        let (service, tenant, store) = setup();
+        let billing = service
+            .create_role(tenant, new_role("BILLING_ADMIN"), &AuditContext::default())
+            .await
+            .unwrap();
+        let support = service
+            .create_role(tenant, new_role("SUPPORT"), &AuditContext::default())
+            .await
+            .unwrap();
+        let invoices = create_permission(&service, tenant, "invoices.write").await;
+        let read = create_permission(&service, tenant, "users.read").await;
+        create_permission(&service, tenant, "users.write").await;
+        for (role, permission) in [(&billing, &invoices), (&billing, &read), (&support, &read)] {
+            service
+                .assign_permission(tenant, role.id, permission.id, &AuditContext::default())
+                .await
+                .unwrap();
         }
-        if input.name != role_exist.name {
-            role_exist.name = input.name;
+        let user = UserStore::create_user(
+            &store,
+            tenant,
+            NewUser {
+                name: "Jane Doe".to_string(),
+                email: "jane@example.com".to_string(),
+                password: "hash".to_string(),
+                role_ids: Vec::new(),
+            },
+        )
+        .await
+        .unwrap();
+        for role in [&billing, &support] {
+            RoleStore::assign(&store, tenant, user.id, role.id)
+                .await
+                .unwrap();
         }
-        role_exist.updated_at = chrono::Utc::now().naive_utc();
 
-        self.repository
-            .update(id, role_exist)
-            .map_err(|_e| RoleError::DatabaseError(format!("Failed to update role with id {}", id)))
+        let codes = service.get_user_permissions(tenant, user.id).await.unwrap();
+        assert_eq!(codes, vec!["invoices.write", "users.read"]);
+
+        service
+            .delete_role(
+                tenant,
+                billing.id,
+                &RoleDeleteQuery {
+                    force: true,
+                    ..RoleDeleteQuery::default()
+                },
+                &IfMatch::Any,
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let codes = service.get_user_permissions(tenant, user.id).await.unwrap();
+        assert_eq!(codes, vec!["users.read"]);
     }
 
-    pub fn delete_role(&self, id: Uuid) -> Result<Role, RoleError> {
-        // First check if role exists
-        self.get_role(id)?;
+    #[tokio::test]
+    async fn roles_inherit_permissions_from_their_ancestors() {
+        let (service, tenant, store) = setup();
+        let manager = service
+            .create_role(tenant, new_role("MANAGER"), &AuditContext::default())
+            .await
+            .unwrap();
+        let admin = service
+            .create_role(
+                tenant,
+                NewRole {
+                    parent_role_id: Some(manager.id),
+                    ..new_role("ADMIN")
+                },
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let read = create_permission(&service, tenant, "users.read").await;
+        let write = create_permission(&service, tenant, "users.write").await;
+        for (role, permission) in [(&manager, &read), (&admin, &write)] {
+            service
+                .assign_permission(tenant, role.id, permission.id, &AuditContext::default())
+                .await
+                .unwrap();
+        }
+        let user = UserStore::create_user(
+            &store,
+            tenant,
+            NewUser {
+                name: "Jane Doe".to_string(),
+                email: "jane@example.com".to_string(),
+                password: "hash".to_string(),
+                role_ids: Vec::new(),
+            },
+        )
+        .await
+        .unwrap();
+        RoleStore::assign(&store, tenant, user.id, admin.id)
+            .await
+            .unwrap();
+
+        let ancestors = service.get_role_ancestors(tenant, admin.id).await.unwrap();
+        assert_eq!(
+            ancestors.iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![manager.id]
+        );
+        let codes = service.get_user_permissions(tenant, user.id).await.unwrap();
+        assert_eq!(codes, vec!["users.read", "users.write"]);
+
+        service
+            .delete_role(
+                tenant,
+                manager.id,
+                &RoleDeleteQuery::default(),
+                &IfMatch::Any,
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let codes = service.get_user_permissions(tenant, user.id).await.unwrap();
+        assert_eq!(codes, vec!["users.write"]);
+        assert!(
+            service
+                .get_role_ancestors(tenant, admin.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn role_hierarchy_rejects_cycles() {
+        let (service, tenant, _) = setup();
+        let mut parent = None;
+        let mut chain = Vec::new();
+        for code in ["TOP", "MIDDLE", "BOTTOM"] {
+            let role = service
+                .create_role(
+                    tenant,
+                    NewRole {
+                        parent_role_id: parent,
+                        ..new_role(code)
+                    },
+                    &AuditContext::default(),
+                )
+                .await
+                .unwrap();
+            parent = Some(role.id);
+            chain.push(role);
+        }
+        let ctx = AuditContext::default();
+        let set_parent = |id: Uuid, parent_role_id: Option<Uuid>| {
+            service.patch_role(
+                tenant,
+                id,
+                RolePatch {
+                    parent_role_id: Some(parent_role_id),
+                    ..RolePatch::default()
+                },
+                &IfMatch::Any,
+                &ctx,
+            )
+        };
 
-        self.repository.delete(id).map_err(|e| match e {
-            DieselError::NotFound => RoleError::NotFound(format!("Role with id {} not found", id)),
-            _ => RoleError::DatabaseError(format!("Failed to delete role with id {}", id)),
+        for parent_id in [chain[2].id, chain[0].id, Uuid::new_v4()] {
+            let err = set_parent(chain[0].id, Some(parent_id)).await.unwrap_err();
+            assert_eq!(field_errors(err), vec!["parent_role_id"]);
+        }
+
+        let detached = set_parent(chain[1].id, None).await.unwrap();
+        assert_eq!(detached.parent_role_id, None);
+        let moved = set_parent(chain[0].id, Some(chain[2].id)).await.unwrap();
+        assert_eq!(moved.parent_role_id, Some(chain[2].id));
+    }
+
+    async fn create_user(
+        store: &InMemoryStore,
+        tenant: Tenant,
+        email: &str,
+        roles: &[&Role],
+    ) -> User {
+        let user = UserStore::create_user(
+            store,
+            tenant,
+            NewUser {
+                name: "Jane Doe".to_string(),
+                email: email.to_string(),
+                password: "hash".to_string(),
+                role_ids: Vec::new(),
+            },
+        )
+        .await
+        .unwrap();
+        for role in roles {
+            RoleStore::assign(store, tenant, user.id, role.id)
+                .await
+                .unwrap();
+        }
+        user
+    }
+
+    #[tokio::test]
+    async fn delete_role_refuses_while_assigned_unless_forced() {
+        let (service, tenant, store) = setup();
+        let role = service
+            .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+            .await
+            .unwrap();
+        let user = create_user(&store, tenant, "jane@example.com", &[&role]).await;
+        create_user(&store, tenant, "john@example.com", &[&role]).await;
+        let (service, ctx, role_id) = (&service, &AuditContext::default(), role.id);
+        let delete = |options: RoleDeleteQuery| async move {
+            service
+                .delete_role(tenant, role_id, &options, &IfMatch::Any, ctx)
+                .await
+        };
+
+        let err = delete(RoleDeleteQuery::default()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            RoleError::InUse {
+                assigned_users: 2,
+                ..
+            }
+        ));
+        assert!(service.get_role(tenant, role.id, false).await.is_ok());
+
+        delete(RoleDeleteQuery {
+            force: true,
+            ..RoleDeleteQuery::default()
         })
+        .await
+        .unwrap();
+        let held = RoleStore::find_by_users(&store, tenant, &[user.id])
+            .await
+            .unwrap();
+        assert!(held.is_empty());
+        let (events, total) = AuditStore::find_all(
+            &store,
+            tenant,
+            &AuditListQuery {
+                entity_id: Some(user.id),
+                ..AuditListQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(
+            events[0].changes,
+            json!({ "role_ids": { "before": [role.id], "after": [] } })
+        );
+
+        service
+            .restore_role(tenant, role.id, &AuditContext::default())
+            .await
+            .unwrap();
+        let held = RoleStore::find_by_users(&store, tenant, &[user.id])
+            .await
+            .unwrap();
+        assert_eq!(held.len(), 1);
+        let (events, total) = AuditStore::find_all(
+            &store,
+            tenant,
+            &AuditListQuery {
+                entity_id: Some(user.id),
+                ..AuditListQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(
+            events[0].changes,
+            json!({ "role_ids": { "before": [], "after": [role.id] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_role_validates_options_without_users() {
+        let (service, tenant, _) = setup();
+        let roles = [
+            service
+                .create_role(tenant, new_role("EDITOR"), &AuditContext::default())
+                .await
+                .unwrap(),
+            service
+                .create_role(tenant, new_role("AUTHOR"), &AuditContext::default())
+                .await
+                .unwrap(),
+        ];
+        let (editor, author) = (&roles[0], &roles[1]);
+        let invalid = [
+            (Uuid::new_v4(), false, vec!["reassign_to"]),
+            (editor.id, false, vec!["reassign_to"]),
+            (author.id, true, vec!["force"]),
+        ];
+
+        for (target, force, fields) in invalid {
+            let err = service
+                .delete_role(
+                    tenant,
+                    editor.id,
+                    &RoleDeleteQuery {
+                        reassign_to: Some(target),
+                        force,
+                    },
+                    &IfMatch::Any,
+                    &AuditContext::default(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(field_errors(err), fields);
+        }
+        assert!(service.get_role(tenant, editor.id, false).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn delete_role_reassigns_its_users() {
+        let (service, tenant, store) = setup();
+        let mut roles = Vec::new();
+        for code in ["EDITOR", "AUTHOR", "SUPPORT"] {
+            roles.push(
+                service
+                    .create_role(tenant, new_role(code), &AuditContext::default())
+                    .await
+                    .unwrap(),
+            );
+        }
+        let (editor, author, support) = (&roles[0], &roles[1], &roles[2]);
+        let jane = create_user(&store, tenant, "jane@example.com", &[editor, support]).await;
+        let john = create_user(&store, tenant, "john@example.com", &[editor, author]).await;
+        let reassign_to = |target: Uuid| RoleDeleteQuery {
+            reassign_to: Some(target),
+            ..RoleDeleteQuery::default()
+        };
+
+        for target in [editor.id, Uuid::new_v4()] {
+            let err = service
+                .delete_role(
+                    tenant,
+                    editor.id,
+                    &reassign_to(target),
+                    &IfMatch::Any,
+                    &AuditContext::default(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(field_errors(err), vec!["reassign_to"]);
+        }
+
+        service
+            .delete_role(
+                tenant,
+                editor.id,
+                &reassign_to(author.id),
+                &IfMatch::Any,
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        let mut held: Vec<(Uuid, Uuid)> =
+            RoleStore::find_by_users(&store, tenant, &[jane.id, john.id])
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(user_id, role)| (user_id, role.id))
+                .collect();
+        held.sort();
+        let mut expected = vec![
+            (jane.id, author.id),
+            (jane.id, support.id),
+            (john.id, author.id),
+        ];
+        expected.sort();
+        assert_eq!(held, expected);
+
+        let (events, total) = AuditStore::find_all(
+            &store,
+            tenant,
+            &AuditListQuery {
+                entity_id: Some(jane.id),
+                ..AuditListQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 1);
+        assert!(events[0].changes.get("role_ids").is_some());
+        let current = UserStore::get_user(&store, tenant, jane.id, false)
+            .await
+            .unwrap();
+        assert!(current.updated_at > jane.updated_at);
     }
 }
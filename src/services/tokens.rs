@@ -0,0 +1,22 @@
+//! Opaque single-use tokens such as refresh, password reset and email
+//! verification tokens.
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Returns 32 random bytes, hex encoded, for use as an opaque token.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+/// Opaque tokens are stored as their SHA-256 hash so a database leak does not
+/// expose usable tokens.
+pub fn hash_token(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
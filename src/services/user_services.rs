@@ -1,115 +1,1943 @@
-use crate::models::user::{NewUser, User};
-use crate::repositories::role_repository::RoleRepository;
-use crate::repositories::user_repository::UserRepository;
-use diesel::result::Error as DieselError;
+use crate::config::auth::AuthConfig;
+use crate::models::audit::{AuditAction, AuditContext, Audited};
+use crate::models::auth::AuthUser;
+use crate::models::email_verification::NewEmailVerificationToken;
+use crate::models::etag::IfMatch;
+use crate::models::organization::Tenant;
+use crate::models::query::UserListQuery;
+use crate::models::role::{ROLE_ADMIN, Role};
+use crate::models::user::{ChangePassword, NewUser, UpdateProfile, User, UserPatch};
+use crate::notifications::{Notification, Notifier};
+use crate::repositories::{
+    LockMode, RepositoryError, RoleStore, Transaction, UnitOfWork, UserStore, in_transaction,
+};
+use crate::services::passwords::{hash_password, verify_password};
+use crate::services::tokens::{generate_token, hash_token};
+use crate::validation::{
+    MAX_VARCHAR_LENGTH, Validate, ValidationErrors, check_email, check_password, check_text,
+};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum UserError {
     DatabaseError(String),
     NotFound(String),
-    ValidationError(String),
+    Conflict {
+        field: String,
+        message: String,
+    },
+    ValidationError(ValidationErrors),
+    /// The caller may not make this change to this user.
+    Forbidden(String),
     HashError(String),
+    /// The `If-Match` precondition failed or the user changed concurrently.
+    PreconditionFailed(String),
+    Unavailable(String),
 }
 
-impl From<DieselError> for UserError {
-    fn from(err: DieselError) -> UserError {
+impl From<RepositoryError> for UserError {
+    fn from(err: RepositoryError) -> UserError {
         match err {
-            DieselError::NotFound => UserError::NotFound("User not found".to_string()),
-            _ => UserError::DatabaseError(format!("Database error: {}", err)),
+            RepositoryError::NotFound => UserError::NotFound("User not found".to_string()),
+            RepositoryError::UniqueViolation(field) => UserError::Conflict {
+                message: format!("A user with this {} already exists", field),
+                field,
+            },
+            RepositoryError::Stale => UserError::PreconditionFailed(STALE_USER.to_string()),
+            RepositoryError::Unavailable(msg) => {
+                UserError::Unavailable(format!("Database is unavailable: {}", msg))
+            }
+            RepositoryError::Database(msg) => {
+                UserError::DatabaseError(format!("Database error: {}", msg))
+            }
         }
     }
 }
 
+const STALE_USER: &str = "User has been modified since it was read";
+
 pub struct UserService {
-    pub repository: UserRepository,
-    pub role_repository: RoleRepository,
+    pub repository: Arc<dyn UserStore>,
+    pub role_repository: Arc<dyn RoleStore>,
+    pub unit_of_work: Arc<dyn UnitOfWork>,
+    pub notifier: Arc<dyn Notifier>,
+    pub config: AuthConfig,
 }
 
 impl UserService {
-    pub fn new(repository: UserRepository, role_repository: RoleRepository) -> Self {
+    pub fn new(
+        repository: Arc<dyn UserStore>,
+        role_repository: Arc<dyn RoleStore>,
+        unit_of_work: Arc<dyn UnitOfWork>,
+        notifier: Arc<dyn Notifier>,
+        config: AuthConfig,
+    ) -> Self {
         UserService {
             repository,
             role_repository,
+            unit_of_work,
+            notifier,
+            config,
         }
     }
 
-    pub fn get_users(&self) -> Result<Vec<User>, UserError> {
+    pub async fn get_users(
+        &self,
+        tenant: Tenant,
+        filter: &UserListQuery,
+    ) -> Result<(Vec<User>, i64), UserError> {
         self.repository
-            .get_users()
-            .map_err(|_| UserError::DatabaseError("Failed to fetch users".to_string()))
+            .get_users(tenant, filter)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::Database(_) => {
+                    UserError::DatabaseError("Failed to fetch users".to_string())
+                }
+                _ => e.into(),
+            })
     }
 
-    pub fn create_user(&self, mut input: NewUser) -> Result<User, UserError> {
-        // Validate role exists
-        self.role_repository
-            .find_by_id(input.role_id)
-            .map_err(|_| {
-                UserError::ValidationError(format!("Role with id {} not found", input.role_id))
-            })?;
+    pub async fn create_user(
+        &self,
+        tenant: Tenant,
+        mut input: NewUser,
+        caller: &AuthUser,
+        ctx: &AuditContext,
+    ) -> Result<User, UserError> {
+        // Hash the password up front so no locks are held meanwhile
+        let mut errors = input.validate().err().unwrap_or_default();
+        if errors.is_empty() {
+            input.password = self
+                .hash_password(std::mem::take(&mut input.password))
+                .await?;
+        }
+
+        let (user, verification) = in_transaction(&*self.unit_of_work, |tx| async move {
+            // Validate payload and role existence
+            let role_ids = std::mem::take(&mut input.role_ids);
+            let granted = check_roles(&*tx, tenant, &role_ids, &[], &mut errors).await?;
+            errors.into_result().map_err(UserError::ValidationError)?;
+            check_grantable(caller, &granted)?;
+
+            // Create user
+            let user = tx
+                .users()
+                .create_user(tenant, input)
+                .await
+                .map_err(|e| match e {
+                    RepositoryError::Database(_) => {
+                        UserError::DatabaseError("Failed to create user".to_string())
+                    }
+                    _ => e.into(),
+                })?;
+            write_roles(&*tx, tenant, user.id, &[], &role_ids).await?;
+            let mut created = user.snapshot();
+            created["role_ids"] = json!(current_role_ids(&*tx, tenant, user.id).await?);
+            tx.audit()
+                .record(
+                    tenant,
+                    ctx.event_with(
+                        AuditAction::Create,
+                        User::ENTITY_TYPE,
+                        user.id,
+                        &Value::Null,
+                        &created,
+                    ),
+                )
+                .await?;
+            let verification = self.issue_verification(&*tx, &user).await?;
+            Ok::<_, UserError>((user, verification))
+        })
+        .await?;
+
+        self.deliver(&user, Some(verification)).await;
+        Ok(user)
+    }
+
+    pub async fn get_user(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        include_deleted: bool,
+    ) -> Result<User, UserError> {
+        self.repository
+            .get_user(tenant, id, include_deleted)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::NotFound => {
+                    UserError::NotFound(format!("User with id {} not found", id))
+                }
+                RepositoryError::Database(_) => {
+                    UserError::DatabaseError("Failed to fetch user".to_string())
+                }
+                _ => e.into(),
+            })
+    }
+
+    /// Replaces every writable field of the user, as `PUT` does.
+    pub async fn update_user(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        mut input: NewUser,
+        if_match: &IfMatch,
+        caller: &AuthUser,
+        ctx: &AuditContext,
+    ) -> Result<User, UserError> {
+        let mut errors = input.validate().err().unwrap_or_default();
+        if errors.is_empty() {
+            input.password = self
+                .hash_password(std::mem::take(&mut input.password))
+                .await?;
+        }
 
-        // Hash password
-        input.password = bcrypt::hash(input.password.as_str(), 10)
-            .map_err(|e| UserError::HashError(format!("Failed to hash password: {}", e)))?;
+        let (user, verification) = in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, if_match).await?;
+            check_target(&*tx, tenant, caller, id).await?;
+            let mut user = before.clone();
 
-        // Create user
-        self.repository.create_user(input).map_err(|e| match e {
-            DieselError::DatabaseError(_, _) => {
-                UserError::DatabaseError("Failed to create user".to_string())
+            let roles = current_role_ids(&*tx, tenant, id).await?;
+
+            let granted = check_roles(&*tx, tenant, &input.role_ids, &roles, &mut errors).await?;
+            errors.into_result().map_err(UserError::ValidationError)?;
+            check_grantable(caller, &granted)?;
+
+            user.name = input.name;
+            let email_changed = set_email(&mut user, input.email);
+            user.password = input.password;
+
+            let saved = self
+                .save(&*tx, tenant, &before, user, email_changed, ctx)
+                .await?;
+            set_roles(&*tx, tenant, id, &roles, &input.role_ids, ctx).await?;
+            Ok::<_, UserError>(saved)
+        })
+        .await?;
+
+        self.deliver(&user, verification).await;
+        Ok(user)
+    }
+
+    /// Changes only the fields present in `input`, as `PATCH` does.
+    pub async fn patch_user(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        mut input: UserPatch,
+        if_match: &IfMatch,
+        caller: &AuthUser,
+        ctx: &AuditContext,
+    ) -> Result<User, UserError> {
+        let mut errors = input.validate().err().unwrap_or_default();
+        if errors.is_empty()
+            && let Some(password) = input.password.take()
+        {
+            input.password = Some(self.hash_password(password).await?);
+        }
+
+        let (user, verification) = in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, if_match).await?;
+            check_target(&*tx, tenant, caller, id).await?;
+            let mut user = before.clone();
+
+            let roles = current_role_ids(&*tx, tenant, id).await?;
+
+            let mut granted = Vec::new();
+            if let Some(role_ids) = &input.role_ids {
+                granted = check_roles(&*tx, tenant, role_ids, &roles, &mut errors).await?;
             }
-            _ => e.into(),
+            errors.into_result().map_err(UserError::ValidationError)?;
+            check_grantable(caller, &granted)?;
+
+            if let Some(name) = input.name {
+                user.name = name;
+            }
+            let email_changed = input.email.is_some_and(|email| set_email(&mut user, email));
+            if let Some(password) = input.password {
+                user.password = password;
+            }
+
+            let saved = self
+                .save(&*tx, tenant, &before, user, email_changed, ctx)
+                .await?;
+            if let Some(role_ids) = &input.role_ids {
+                set_roles(&*tx, tenant, id, &roles, role_ids, ctx).await?;
+            }
+            Ok::<_, UserError>(saved)
+        })
+        .await?;
+
+        self.deliver(&user, verification).await;
+        Ok(user)
+    }
+
+    /// Lets the caller change their own name and email. Omitted fields are
+    /// left unchanged.
+    pub async fn update_profile(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        input: UpdateProfile,
+        ctx: &AuditContext,
+    ) -> Result<User, UserError> {
+        let mut errors = ValidationErrors::new();
+        if let Some(name) = &input.name {
+            check_text(&mut errors, "name", name, MAX_VARCHAR_LENGTH);
+        }
+        if let Some(email) = &input.email {
+            check_email(&mut errors, "email", email);
+        }
+        errors.into_result().map_err(UserError::ValidationError)?;
+
+        let (user, verification) = in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, &IfMatch::Any).await?;
+            let mut user = before.clone();
+            if let Some(name) = input.name {
+                user.name = name;
+            }
+            let email_changed = input.email.is_some_and(|email| set_email(&mut user, email));
+
+            self.save(&*tx, tenant, &before, user, email_changed, ctx)
+                .await
         })
+        .await?;
+
+        self.deliver(&user, verification).await;
+        Ok(user)
     }
 
-    pub fn get_user(&self, id: Uuid) -> Result<User, UserError> {
-        self.repository.get_user(id).map_err(|e| match e {
-            DieselError::NotFound => UserError::NotFound(format!("User with id {} not found", id)),
-            _ => UserError::DatabaseError("Failed to fetch user".to_string()),
+    /// Changes the caller's password after checking their current one, and
+    /// signs the user out of every session by revoking their refresh tokens.
+    pub async fn change_password(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        input: ChangePassword,
+        ctx: &AuditContext,
+    ) -> Result<(), UserError> {
+        // Check and hash the passwords against a snapshot, then lock only
+        // to write, failing if the password changed in between
+        let current = self.get_user(tenant, id, false).await?;
+        let mut errors = ValidationErrors::new();
+        let current_matches = verify_password(input.current_password, current.password.clone())
+            .await
+            .map_err(UserError::HashError)?;
+        if !current_matches {
+            errors.add("current_password", "current_password is incorrect");
+        }
+        check_password(&mut errors, "new_password", &input.new_password);
+        errors.into_result().map_err(UserError::ValidationError)?;
+        let password = self.hash_password(input.new_password).await?;
+
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, &IfMatch::Any).await?;
+            if before.password != current.password {
+                return Err(UserError::PreconditionFailed(STALE_USER.to_string()));
+            }
+
+            let mut user = before.clone();
+            user.password = password;
+            self.save(&*tx, tenant, &before, user, false, ctx).await?;
+            tx.refresh_tokens().revoke_user(id).await?;
+            Ok(())
         })
+        .await
     }
 
-    pub fn update_user(&self, id: Uuid, input: NewUser) -> Result<User, UserError> {
-        // Check if user exists
-        let mut user_exist = self
-            .repository
-            .get_user(id)
-            .map_err(|_| UserError::NotFound(format!("User with id {} not found", id)))?;
-
-        // Validate role if changed
-        if input.role_id != user_exist.role_id {
-            self.role_repository
-                .find_by_id(input.role_id)
-                .map_err(|_| {
-                    UserError::ValidationError(format!("Role with id {} not found", input.role_id))
+    pub async fn delete_user(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        if_match: &IfMatch,
+        caller: &AuthUser,
+        ctx: &AuditContext,
+    ) -> Result<User, UserError> {
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, if_match).await?;
+            check_target(&*tx, tenant, caller, id).await?;
+            let user = tx
+                .users()
+                .delete_user(tenant, id, before.updated_at)
+                .await
+                .map_err(|e| match e {
+                    RepositoryError::NotFound => {
+                        UserError::NotFound(format!("User with id {} not found", id))
+                    }
+                    RepositoryError::Database(_) => {
+                        UserError::DatabaseError(format!("Failed to delete user with id {}", id))
+                    }
+                    _ => e.into(),
                 })?;
-            user_exist.role_id = input.role_id;
+            tx.audit()
+                .record(tenant, ctx.event(AuditAction::Delete, Some(&before), &user))
+                .await?;
+            Ok(user)
+        })
+        .await
+    }
+
+    pub async fn restore_user(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        caller: &AuthUser,
+        ctx: &AuditContext,
+    ) -> Result<User, UserError> {
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            check_target(&*tx, tenant, caller, id).await?;
+            let before = tx.users().get_user(tenant, id, true).await;
+            let user = tx
+                .users()
+                .restore_user(tenant, id)
+                .await
+                .map_err(|e| match e {
+                    RepositoryError::NotFound => {
+                        UserError::NotFound(format!("Deleted user with id {} not found", id))
+                    }
+                    RepositoryError::Database(_) => {
+                        UserError::DatabaseError(format!("Failed to restore user with id {}", id))
+                    }
+                    _ => e.into(),
+                })?;
+            tx.audit()
+                .record(
+                    tenant,
+                    ctx.event(AuditAction::Restore, before.ok().as_ref(), &user),
+                )
+                .await?;
+            Ok(user)
+        })
+        .await
+    }
+
+    /// The live roles assigned to each of `users`, keyed by user id.
+    ///
+    /// This is one query for the whole batch, run after the one that loaded
+    /// `users`, rather than a join in that query: a user holding several
+    /// roles would otherwise span several rows and break the pagination of
+    /// user lists. Every user response carries `role_ids`, so the lookup is
+    /// needed whether or not the roles themselves are embedded.
+    pub async fn get_user_roles(
+        &self,
+        tenant: Tenant,
+        users: &[User],
+    ) -> Result<HashMap<Uuid, Vec<Role>>, UserError> {
+        let user_ids: Vec<Uuid> = users.iter().map(|user| user.id).collect();
+        let rows = self
+            .role_repository
+            .find_by_users(tenant, &user_ids)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::Database(_) => {
+                    UserError::DatabaseError("Failed to fetch roles of users".to_string())
+                }
+                _ => e.into(),
+            })?;
+        let mut roles: HashMap<Uuid, Vec<Role>> = HashMap::new();
+        for (user_id, role) in rows {
+            roles.entry(user_id).or_default().push(role);
         }
+        Ok(roles)
+    }
+
+    /// Adds a role to those assigned to the user and returns the user's
+    /// roles. Assigning a role twice changes nothing.
+    pub async fn assign_role(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        role_id: Uuid,
+        caller: &AuthUser,
+        ctx: &AuditContext,
+    ) -> Result<Vec<Role>, UserError> {
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, &IfMatch::Any).await?;
+            check_target(&*tx, tenant, caller, id).await?;
+            let role = match tx.roles().lock(tenant, role_id, LockMode::Share).await {
+                Ok(role) => role,
+                Err(RepositoryError::NotFound) => {
+                    return Err(UserError::NotFound(format!(
+                        "Role with id {} not found",
+                        role_id
+                    )));
+                }
+                Err(e) => return Err(e.into()),
+            };
 
-        // Update fields if provided
-        if !input.name.is_empty() {
-            user_exist.name = input.name;
+            let roles = current_role_ids(&*tx, tenant, id).await?;
+            if !roles.contains(&role_id) {
+                check_grantable(caller, &[role])?;
+                let mut assigned = roles.clone();
+                assigned.push(role_id);
+                set_roles(&*tx, tenant, id, &roles, &assigned, ctx).await?;
+                touch(&*tx, tenant, before).await?;
+            }
+            user_roles(&*tx, tenant, id).await
+        })
+        .await
+    }
+
+    /// Removes a role from those assigned to the user and returns the user's
+    /// remaining roles.
+    pub async fn revoke_role(
+        &self,
+        tenant: Tenant,
+        id: Uuid,
+        role_id: Uuid,
+        caller: &AuthUser,
+        ctx: &AuditContext,
+    ) -> Result<Vec<Role>, UserError> {
+        in_transaction(&*self.unit_of_work, |tx| async move {
+            let before = lock_current(&*tx, tenant, id, &IfMatch::Any).await?;
+            check_target(&*tx, tenant, caller, id).await?;
+            let roles = current_role_ids(&*tx, tenant, id).await?;
+            if !roles.contains(&role_id) {
+                return Err(UserError::NotFound(format!(
+                    "Role with id {} is not assigned to user {}",
+                    role_id, id
+                )));
+            }
+
+            let remaining: Vec<Uuid> = roles.iter().copied().filter(|r| *r != role_id).collect();
+            set_roles(&*tx, tenant, id, &roles, &remaining, ctx).await?;
+            touch(&*tx, tenant, before).await?;
+            user_roles(&*tx, tenant, id).await
+        })
+        .await
+    }
+
+    async fn hash_password(&self, password: String) -> Result<String, UserError> {
+        hash_password(password, self.config.bcrypt_cost)
+            .await
+            .map_err(UserError::HashError)
+    }
+
+    /// Writes the modified user back and audits the change from `before`.
+    /// When the email address changed, also issues a verification token and
+    /// returns the message carrying it.
+    async fn save(
+        &self,
+        tx: &dyn Transaction,
+        tenant: Tenant,
+        before: &User,
+        mut user: User,
+        email_changed: bool,
+        ctx: &AuditContext,
+    ) -> Result<(User, Option<Notification>), UserError> {
+        let id = before.id;
+        user.updated_at = chrono::Utc::now().naive_utc();
+        let user = tx
+            .users()
+            .update_user(tenant, id, user, before.updated_at)
+            .await
+            .map_err(|e| match e {
+                RepositoryError::NotFound => {
+                    UserError::NotFound(format!("User with id {} not found", id))
+                }
+                RepositoryError::Database(_) => {
+                    UserError::DatabaseError(format!("Failed to update user with id {}", id))
+                }
+                _ => e.into(),
+            })?;
+        tx.audit()
+            .record(tenant, ctx.event(AuditAction::Update, Some(before), &user))
+            .await?;
+
+        let verification = match email_changed {
+            true => Some(self.issue_verification(tx, &user).await?),
+            false => None,
+        };
+        Ok((user, verification))
+    }
+
+    /// Issues a verification token for the user's current email address and
+    /// returns the message with its link. Earlier tokens stop working.
+    async fn issue_verification(
+        &self,
+        tx: &dyn Transaction,
+        user: &User,
+    ) -> Result<Notification, UserError> {
+        tx.email_verifications().invalidate_user(user.id).await?;
+        let token = generate_token();
+        tx.email_verifications()
+            .create(NewEmailVerificationToken {
+                user_id: user.id,
+                email: user.email.clone(),
+                token_hash: hash_token(&token),
+                expires_at: chrono::Utc::now().naive_utc()
+                    + chrono::Duration::seconds(self.config.email_verification_ttl),
+            })
+            .await?;
+
+        let link = format!("{}?token={}", self.config.email_verification_url, token);
+        Ok(Notification {
+            to: user.email.clone(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Hello {},\n\nPlease confirm your email address by opening the link below.\n\n{}",
+                user.name, link
+            ),
+        })
+    }
+
+    /// Sends a verification link once the transaction that issued it has
+    /// committed, so no link is sent for a change that was rolled back.
+    async fn deliver(&self, user: &User, verification: Option<Notification>) {
+        if let Some(notification) = verification
+            && let Err(e) = self.notifier.send(notification).await
+        {
+            tracing::error!(user_id = %user.id, "Failed to send verification link: {}", e);
         }
-        if !input.email.is_empty() {
-            user_exist.email = input.email;
+    }
+}
+
+/// Fetches and locks the user a write is about to change, checking that the
+/// caller's `If-Match` names its current version.
+async fn lock_current(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+    if_match: &IfMatch,
+) -> Result<User, UserError> {
+    let user = tx
+        .users()
+        .lock_user(tenant, id, LockMode::Update)
+        .await
+        .map_err(|e| match e {
+            RepositoryError::NotFound => {
+                UserError::NotFound(format!("User with id {} not found", id))
+            }
+            RepositoryError::Database(_) => {
+                UserError::DatabaseError("Failed to fetch user".to_string())
+            }
+            _ => e.into(),
+        })?;
+    if !if_match.matches(user.updated_at) {
+        return Err(UserError::PreconditionFailed(STALE_USER.to_string()));
+    }
+    Ok(user)
+}
+
+/// Records a `role_ids` validation error for every role in `role_ids` that
+/// is not assigned yet and does not exist or is soft-deleted, and returns
+/// the roles that are about to be granted. Those stay locked against
+/// deletion until the transaction ends.
+async fn check_roles(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    role_ids: &[Uuid],
+    assigned: &[Uuid],
+    errors: &mut ValidationErrors,
+) -> Result<Vec<Role>, UserError> {
+    let mut granted = Vec::new();
+    for (i, role_id) in role_ids.iter().enumerate() {
+        if assigned.contains(role_id) || role_ids[..i].contains(role_id) {
+            continue;
         }
-        if !input.password.is_empty() {
-            user_exist.password = bcrypt::hash(input.password.as_str(), 10)
-                .map_err(|e| UserError::HashError(format!("Failed to hash password: {}", e)))?;
+        match tx.roles().lock(tenant, *role_id, LockMode::Share).await {
+            Ok(role) => granted.push(role),
+            Err(RepositoryError::NotFound) => {
+                errors.add("role_ids", format!("Role with id {} not found", role_id));
+            }
+            Err(e) => return Err(e.into()),
         }
+    }
+    Ok(granted)
+}
 
-        // Update user
-        self.repository
-            .update_user(id, user_exist)
-            .map_err(|_| UserError::DatabaseError(format!("Failed to update user with id {}", id)))
+/// Keeps non-administrators from managing administrators, whose accounts
+/// they could otherwise take over. Roles inherited from `ADMIN` count.
+async fn check_target(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    caller: &AuthUser,
+    id: Uuid,
+) -> Result<(), UserError> {
+    if caller.is_admin() {
+        return Ok(());
+    }
+    for role in user_roles(tx, tenant, id).await? {
+        let ancestors = tx.roles().find_ancestors(tenant, role.id, false).await?;
+        if std::iter::once(&role)
+            .chain(&ancestors)
+            .any(|role| role.code == ROLE_ADMIN)
+        {
+            return Err(UserError::Forbidden(
+                "Only administrators can manage administrators".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Keeps non-administrators from granting roles they do not hold
+/// themselves, which would raise privileges above their own.
+fn check_grantable(caller: &AuthUser, granted: &[Role]) -> Result<(), UserError> {
+    if caller.is_admin() {
+        return Ok(());
+    }
+    match granted.iter().find(|role| !caller.has_role(&role.code)) {
+        Some(role) => Err(UserError::Forbidden(format!(
+            "Only administrators can assign the {} role without holding it",
+            role.code
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The live roles assigned to a user, ordered by code.
+async fn user_roles(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+) -> Result<Vec<Role>, UserError> {
+    let rows = tx.roles().find_by_users(tenant, &[id]).await?;
+    Ok(rows.into_iter().map(|(_, role)| role).collect())
+}
+
+/// The ids of the live roles assigned to a user.
+async fn current_role_ids(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+) -> Result<Vec<Uuid>, UserError> {
+    let roles = user_roles(tx, tenant, id).await?;
+    Ok(roles.into_iter().map(|role| role.id).collect())
+}
+
+/// Changes the roles assigned to a user from `assigned` to `role_ids`,
+/// returning whether anything changed.
+async fn write_roles(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+    assigned: &[Uuid],
+    role_ids: &[Uuid],
+) -> Result<bool, UserError> {
+    let mut changed = false;
+    for role_id in role_ids.iter().filter(|r| !assigned.contains(r)) {
+        changed |= tx.roles().assign(tenant, id, *role_id).await? > 0;
+    }
+    for role_id in assigned.iter().filter(|r| !role_ids.contains(r)) {
+        changed |= tx.roles().unassign(tenant, id, *role_id).await? > 0;
+    }
+    Ok(changed)
+}
+
+/// Like [`write_roles`], auditing the change as an update of the user's
+/// `role_ids`.
+async fn set_roles(
+    tx: &dyn Transaction,
+    tenant: Tenant,
+    id: Uuid,
+    assigned: &[Uuid],
+    role_ids: &[Uuid],
+    ctx: &AuditContext,
+) -> Result<(), UserError> {
+    if write_roles(tx, tenant, id, assigned, role_ids).await? {
+        let after = current_role_ids(tx, tenant, id).await?;
+        tx.audit()
+            .record(
+                tenant,
+                ctx.event_with(
+                    AuditAction::Update,
+                    User::ENTITY_TYPE,
+                    id,
+                    &json!({ "role_ids": assigned }),
+                    &json!({ "role_ids": after }),
+                ),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Bumps the user's `updated_at` after a change to their roles, so the
+/// user's entity tag changes with them.
+async fn touch(tx: &dyn Transaction, tenant: Tenant, before: User) -> Result<User, UserError> {
+    let expected = before.updated_at;
+    let mut user = before;
+    user.updated_at = chrono::Utc::now().naive_utc();
+    Ok(tx
+        .users()
+        .update_user(tenant, user.id, user, expected)
+        .await?)
+}
+
+/// Sets the user's email address. A different address has to be verified
+/// again, so this clears `email_verified_at` and returns `true`; a change of
+/// case only keeps the verification.
+fn set_email(user: &mut User, email: String) -> bool {
+    let changed = email.to_lowercase() != user.email.to_lowercase();
+    user.email = email;
+    if changed {
+        user.email_verified_at = None;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtos::user_dto::UserResponse;
+    use crate::errors::AppError;
+    use crate::models::etag::etag;
+    use crate::models::query::{AuditListQuery, MAX_PAGE_LIMIT, UserInclude, UserQuery};
+    use crate::models::refresh_token::NewRefreshToken;
+    use crate::models::role::{NewRole, ROLE_USER_MANAGER};
+    use crate::notifications::RecordingNotifier;
+    use crate::repositories::memory::InMemoryStore;
+    use crate::repositories::{AuditStore, RefreshTokenStore, RoleStore};
+    use axum::http::StatusCode;
+
+    async fn setup() -> (UserService, Tenant, Uuid, RecordingNotifier) {
+        let (service, tenant, role_id, notifier, _) = setup_with_store().await;
+        (service, tenant, role_id, notifier)
+    }
+
+    async fn setup_with_store() -> (UserService, Tenant, Uuid, RecordingNotifier, InMemoryStore) {
+        let store = InMemoryStore::new();
+        let tenant = store.create_organization("acme");
+        let role = RoleStore::create(
+            &store,
+            tenant,
+            NewRole {
+                name: "Viewer".to_string(),
+                code: "VIEWER".to_string(),
+                description: String::new(),
+                parent_role_id: None,
+            },
+        )
+        .await
+        .unwrap();
+        let notifier = RecordingNotifier::default();
+        let config = AuthConfig {
+            bcrypt_cost: 4,
+            ..AuthConfig::default()
+        };
+        let service = UserService::new(
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+            Arc::new(store.clone()),
+            Arc::new(notifier.clone()),
+            config,
+        );
+        (service, tenant, role.id, notifier, store)
+    }
+
+    fn new_user(email: &str, role_id: Uuid) -> NewUser {
+        NewUser {
+            name: "Jane Doe".to_string(),
+            email: email.to_string(),
+            password: "secret123".to_string(),
+            role_ids: vec![role_id],
+        }
+    }
+
+    /// A caller holding the roles with `codes`.
+    fn caller(codes: &[&str]) -> AuthUser {
+        AuthUser {
+            id: Uuid::new_v4(),
+            organization_id: Uuid::nil(),
+            role_codes: codes.iter().map(|code| code.to_string()).collect(),
+        }
+    }
+
+    fn admin() -> AuthUser {
+        caller(&[ROLE_ADMIN])
+    }
+
+    fn field_errors(err: UserError) -> Vec<String> {
+        match err {
+            UserError::ValidationError(errors) => serde_json::to_value(errors)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect(),
+            other => panic!("expected validation error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn create_user_hashes_password() {
+        let (service, tenant, role_id, _) = setup().await;
+
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_ne!(user.password, "secret123");
+        assert!(bcrypt::verify("secret123", &user.password).unwrap());
+    }
+
+    #[tokio::test]
+    async fn create_user_rejects_unknown_role() {
+        let (service, tenant, _, _) = setup().await;
+
+        let err = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", Uuid::new_v4()),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(field_errors(err), vec!["role_ids"]);
+    }
+
+    #[tokio::test]
+    async fn create_user_rejects_deleted_role() {
+        let (service, tenant, role_id, _, store) = setup_with_store().await;
+        let role = RoleStore::find_by_id(&store, tenant, role_id, false).await;
+        RoleStore::delete(&store, tenant, role_id, role.unwrap().updated_at)
+            .await
+            .unwrap();
+
+        let err = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(field_errors(err), vec!["role_ids"]);
+    }
+
+    #[tokio::test]
+    async fn create_user_reports_every_invalid_field() {
+        let (service, tenant, _, _) = setup().await;
+        let input = NewUser {
+            name: String::new(),
+            email: "not-an-email".to_string(),
+            password: "short".to_string(),
+            role_ids: vec![Uuid::new_v4()],
+        };
+
+        let err = service
+            .create_user(tenant, input, &admin(), &AuditContext::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            field_errors(err),
+            vec!["email", "name", "password", "role_ids"]
+        );
+    }
+
+    #[tokio::test]
+    async fn create_user_rejects_duplicate_email_case_insensitively() {
+        let (service, tenant, role_id, _) = setup().await;
+        service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        let err = service
+            .create_user(
+                tenant,
+                new_user("JANE@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, UserError::Conflict { field, .. } if field == "email"));
+    }
+
+    #[tokio::test]
+    async fn update_user_requires_every_field() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let input = NewUser {
+            name: "Janet Doe".to_string(),
+            email: String::new(),
+            password: String::new(),
+            role_ids: vec![role_id],
+        };
+
+        let err = service
+            .update_user(
+                tenant,
+                user.id,
+                input,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(field_errors(err), vec!["email", "password"]);
     }
 
-    pub fn delete_user(&self, id: Uuid) -> Result<User, UserError> {
-        // Check if user exists first
-        self.get_user(id)?;
+    #[tokio::test]
+    async fn patch_user_keeps_omitted_fields() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let input = UserPatch {
+            name: Some("Janet Doe".to_string()),
+            ..UserPatch::default()
+        };
+
+        let updated = service
+            .patch_user(
+                tenant,
+                user.id,
+                input,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(updated.name, "Janet Doe");
+        assert_eq!(updated.email, "jane@example.com");
+        assert_eq!(updated.password, user.password);
+    }
+
+    #[tokio::test]
+    async fn patch_user_rehashes_new_password() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let input = UserPatch {
+            password: Some("another-secret9".to_string()),
+            ..UserPatch::default()
+        };
+
+        let updated = service
+            .patch_user(
+                tenant,
+                user.id,
+                input,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(bcrypt::verify("another-secret9", &updated.password).unwrap());
+    }
+
+    #[tokio::test]
+    async fn patch_user_validates_given_fields() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let input = UserPatch {
+            name: Some(" ".to_string()),
+            role_ids: Some(vec![Uuid::new_v4()]),
+            ..UserPatch::default()
+        };
+
+        let err = service
+            .patch_user(
+                tenant,
+                user.id,
+                input,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(field_errors(err), vec!["name", "role_ids"]);
+    }
+
+    #[tokio::test]
+    async fn writes_require_matching_etag() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let current = IfMatch::parse(&etag(user.updated_at));
+        let rename = || UserPatch {
+            name: Some("Janet Doe".to_string()),
+            ..UserPatch::default()
+        };
+
+        let renamed = service
+            .patch_user(
+                tenant,
+                user.id,
+                rename(),
+                &current,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service
+                .patch_user(
+                    tenant,
+                    user.id,
+                    rename(),
+                    &current,
+                    &admin(),
+                    &AuditContext::default()
+                )
+                .await,
+            Err(UserError::PreconditionFailed(_))
+        ));
+        assert!(matches!(
+            service
+                .delete_user(
+                    tenant,
+                    user.id,
+                    &current,
+                    &admin(),
+                    &AuditContext::default()
+                )
+                .await,
+            Err(UserError::PreconditionFailed(_))
+        ));
+        let latest = IfMatch::parse(&format!("\"stale\", {}", etag(renamed.updated_at)));
+        service
+            .delete_user(tenant, user.id, &latest, &admin(), &AuditContext::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_write_is_rejected_as_stale() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let read = service.get_user(tenant, user.id, false).await.unwrap();
+        service
+            .patch_user(
+                tenant,
+                user.id,
+                UserPatch {
+                    name: Some("Janet Doe".to_string()),
+                    ..UserPatch::default()
+                },
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        let expected = read.updated_at;
+        let err = service
+            .repository
+            .update_user(tenant, user.id, read, expected)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, RepositoryError::Stale));
+    }
+
+    #[tokio::test]
+    async fn deleted_users_are_hidden_until_restored() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        service
+            .delete_user(
+                tenant,
+                user.id,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.get_user(tenant, user.id, false).await,
+            Err(UserError::NotFound(_))
+        ));
+        assert!(service.get_user(tenant, user.id, true).await.is_ok());
+
+        service
+            .restore_user(tenant, user.id, &admin(), &AuditContext::default())
+            .await
+            .unwrap();
+        assert!(service.get_user(tenant, user.id, false).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn get_users_filters_and_paginates() {
+        let (service, tenant, role_id, _) = setup().await;
+        for email in ["a@example.com", "b@example.com", "c@other.org"] {
+            service
+                .create_user(
+                    tenant,
+                    new_user(email, role_id),
+                    &admin(),
+                    &AuditContext::default(),
+                )
+                .await
+                .unwrap();
+        }
+        let filter = UserListQuery {
+            email: Some("EXAMPLE".to_string()),
+            limit: Some(1),
+            ..Default::default()
+        };
+
+        let (users, total) = service.get_users(tenant, &filter).await.unwrap();
+
+        assert_eq!(total, 2);
+        assert_eq!(users.len(), 1);
+
+        let beyond = UserListQuery {
+            page: Some(i64::MAX),
+            limit: Some(MAX_PAGE_LIMIT),
+            ..Default::default()
+        };
+        let (users, total) = service.get_users(tenant, &beyond).await.unwrap();
+        assert_eq!(total, 3);
+        assert!(users.is_empty());
+    }
+
+    async fn create_role(store: &InMemoryStore, tenant: Tenant, code: &str) -> Role {
+        RoleStore::create(
+            store,
+            tenant,
+            NewRole {
+                name: code.to_string(),
+                code: code.to_string(),
+                description: String::new(),
+                parent_role_id: None,
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    fn ids(roles: &[Role]) -> Vec<Uuid> {
+        roles.iter().map(|role| role.id).collect()
+    }
 
-        self.repository.delete_user(id).map_err(|e| match e {
-            DieselError::NotFound => UserError::NotFound(format!("User with id {} not found", id)),
-            _ => UserError::DatabaseError(format!("Failed to delete user with id {}", id)),
+    #[tokio::test]
+    async fn users_can_hold_several_roles() {
+        let (service, tenant, viewer, _, store) = setup_with_store().await;
+        let support = create_role(&store, tenant, "SUPPORT").await;
+        let mut input = new_user("jane@example.com", viewer);
+        input.role_ids = vec![support.id, Uuid::new_v4(), viewer, support.id];
+
+        let err = service
+            .create_user(tenant, input, &admin(), &AuditContext::default())
+            .await
+            .unwrap_err();
+        assert_eq!(field_errors(err), vec!["role_ids"]);
+
+        let mut input = new_user("jane@example.com", viewer);
+        input.role_ids = vec![viewer, support.id, support.id];
+        let user = service
+            .create_user(tenant, input, &admin(), &AuditContext::default())
+            .await
+            .unwrap();
+        let roles = service
+            .get_user_roles(tenant, std::slice::from_ref(&user))
+            .await
+            .unwrap();
+        assert_eq!(ids(&roles[&user.id]), vec![support.id, viewer]);
+
+        let patch = UserPatch {
+            role_ids: Some(vec![support.id]),
+            ..UserPatch::default()
+        };
+        let user = service
+            .patch_user(
+                tenant,
+                user.id,
+                patch,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let roles = service
+            .get_user_roles(tenant, std::slice::from_ref(&user))
+            .await
+            .unwrap();
+        assert_eq!(ids(&roles[&user.id]), vec![support.id]);
+    }
+
+    /// Parses a query string as the handlers' query extractor does.
+    fn query<T: serde::de::DeserializeOwned>(query: &str) -> T {
+        let uri = format!("/users?{}", query).parse().unwrap();
+        axum::extract::Query::<T>::try_from_uri(&uri).unwrap().0
+    }
+
+    /// The responses for `users` as the user endpoints serialize them.
+    async fn responses(
+        service: &UserService,
+        tenant: Tenant,
+        users: Vec<User>,
+        include: Option<UserInclude>,
+    ) -> Vec<Value> {
+        let roles = service.get_user_roles(tenant, &users).await.unwrap();
+        UserResponse::for_users(users, roles, include)
+            .into_iter()
+            .map(|response| serde_json::to_value(response).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn responses_embed_live_roles_when_included() {
+        let (service, tenant, viewer, _, store) = setup_with_store().await;
+        let support = create_role(&store, tenant, "SUPPORT").await;
+        let mut input = new_user("jane@example.com", viewer);
+        input.role_ids = vec![viewer, support.id];
+        let user = service
+            .create_user(tenant, input, &admin(), &AuditContext::default())
+            .await
+            .unwrap();
+        RoleStore::delete(&store, tenant, support.id, support.updated_at)
+            .await
+            .unwrap();
+
+        for include in ["", "include=role", "include=roles"] {
+            let filter: UserListQuery = query(include);
+            let (users, _) = service.get_users(tenant, &filter).await.unwrap();
+            let listed = responses(&service, tenant, users, filter.include).await;
+            let filter: UserQuery = query(include);
+            let found = service
+                .get_user(tenant, user.id, filter.include_deleted)
+                .await
+                .unwrap();
+            let found = responses(&service, tenant, vec![found], filter.include).await;
+
+            for response in [&listed[0], &found[0]] {
+                assert_eq!(response["id"], json!(user.id));
+                assert_eq!(response["role_ids"], json!([viewer]));
+                if include.is_empty() {
+                    assert!(response.get("roles").is_none());
+                } else {
+                    let roles = response["roles"].as_array().unwrap();
+                    assert_eq!(roles.len(), 1);
+                    assert_eq!(roles[0]["id"], json!(viewer));
+                    assert_eq!(roles[0]["code"], json!("VIEWER"));
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn user_managers_cannot_escalate_privileges() {
+        let (service, tenant, viewer, _, store) = setup_with_store().await;
+        let admin_role = create_role(&store, tenant, ROLE_ADMIN).await;
+        let manager_role = create_role(&store, tenant, ROLE_USER_MANAGER).await;
+        let manager = caller(&[ROLE_USER_MANAGER]);
+        let ctx = AuditContext::default();
+        let user = service
+            .create_user(tenant, new_user("jane@example.com", viewer), &admin(), &ctx)
+            .await
+            .unwrap();
+
+        let err = service
+            .assign_role(tenant, user.id, admin_role.id, &manager, &ctx)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UserError::Forbidden(_)));
+        assert_eq!(AppError::from(err).status, StatusCode::FORBIDDEN);
+        let err = service
+            .patch_user(
+                tenant,
+                user.id,
+                UserPatch {
+                    role_ids: Some(vec![viewer, admin_role.id]),
+                    ..UserPatch::default()
+                },
+                &IfMatch::Any,
+                &manager,
+                &ctx,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UserError::Forbidden(_)));
+        let mut input = new_user("john@example.com", admin_role.id);
+        input.role_ids.push(manager_role.id);
+        let err = service
+            .create_user(tenant, input, &manager, &ctx)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UserError::Forbidden(_)));
+
+        // Roles the manager holds can be handed out, to non-administrators only
+        service
+            .assign_role(tenant, user.id, manager_role.id, &manager, &ctx)
+            .await
+            .unwrap();
+        service
+            .assign_role(tenant, user.id, admin_role.id, &admin(), &ctx)
+            .await
+            .unwrap();
+        let err = service
+            .patch_user(
+                tenant,
+                user.id,
+                UserPatch {
+                    password: Some("taken-over".to_string()),
+                    ..UserPatch::default()
+                },
+                &IfMatch::Any,
+                &manager,
+                &ctx,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UserError::Forbidden(_)));
+        let err = service
+            .revoke_role(tenant, user.id, admin_role.id, &manager, &ctx)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UserError::Forbidden(_)));
+    }
+
+    #[tokio::test]
+    async fn roles_are_assigned_and_revoked_one_at_a_time() {
+        let (service, tenant, viewer, _, store) = setup_with_store().await;
+        let support = create_role(&store, tenant, "SUPPORT").await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", viewer),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let ctx = AuditContext::default();
+
+        let roles = service
+            .assign_role(tenant, user.id, support.id, &admin(), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(ids(&roles), vec![support.id, viewer]);
+        let again = service
+            .assign_role(tenant, user.id, support.id, &admin(), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(ids(&again), ids(&roles));
+        let current = service.get_user(tenant, user.id, false).await.unwrap();
+        assert_ne!(current.updated_at, user.updated_at);
+
+        let roles = service
+            .revoke_role(tenant, user.id, viewer, &admin(), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(ids(&roles), vec![support.id]);
+        assert!(matches!(
+            service
+                .revoke_role(tenant, user.id, viewer, &admin(), &ctx)
+                .await,
+            Err(UserError::NotFound(_))
+        ));
+        assert!(matches!(
+            service
+                .assign_role(tenant, user.id, Uuid::new_v4(), &admin(), &ctx)
+                .await,
+            Err(UserError::NotFound(_))
+        ));
+
+        let (events, _) = AuditStore::find_all(
+            &store,
+            tenant,
+            &AuditListQuery {
+                action: Some("update".to_string()),
+                ..AuditListQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].changes,
+            serde_json::json!({
+                "role_ids": { "before": [support.id, viewer], "after": [support.id] },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn users_are_invisible_to_other_organizations() {
+        let (service, tenant, role_id, _, store) = setup_with_store().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let other = store.create_organization("globex");
+
+        assert!(matches!(
+            service.get_user(other, user.id, true).await,
+            Err(UserError::NotFound(_))
+        ));
+        let (users, total) = service
+            .get_users(other, &UserListQuery::default())
+            .await
+            .unwrap();
+        assert!(users.is_empty());
+        assert_eq!(total, 0);
+        let err = service
+            .delete_user(
+                other,
+                user.id,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UserError::NotFound(_)));
+        assert!(service.get_user(tenant, user.id, false).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn organizations_have_separate_emails_and_roles() {
+        let (service, tenant, role_id, _, store) = setup_with_store().await;
+        service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let other = store.create_organization("globex");
+
+        let err = service
+            .create_user(
+                other,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(field_errors(err), vec!["role_ids"]);
+
+        let other_role = RoleStore::create(
+            &store,
+            other,
+            NewRole {
+                name: "Viewer".to_string(),
+                code: "VIEWER".to_string(),
+                description: String::new(),
+                parent_role_id: None,
+            },
+        )
+        .await
+        .unwrap();
+        let user = service
+            .create_user(
+                other,
+                new_user("jane@example.com", other_role.id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(user.organization_id, other.id());
+    }
+
+    #[tokio::test]
+    async fn create_user_sends_verification_link() {
+        let (service, tenant, role_id, notifier) = setup().await;
+
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(user.email_verified_at.is_none());
+        let sent = notifier.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "jane@example.com");
+        assert!(sent[0].body.contains("?token="));
+    }
+
+    #[tokio::test]
+    async fn changing_email_requires_verification_again() {
+        let (service, tenant, role_id, notifier) = setup().await;
+        let mut user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        user.email_verified_at = Some(chrono::Utc::now().naive_utc());
+        let expected = user.updated_at;
+        let user = service
+            .repository
+            .update_user(tenant, user.id, user, expected)
+            .await
+            .unwrap();
+        let rename = UserPatch {
+            name: Some("Janet Doe".to_string()),
+            email: Some("JANE@example.com".to_string()),
+            ..UserPatch::default()
+        };
+
+        let renamed = service
+            .patch_user(
+                tenant,
+                user.id,
+                rename,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        assert!(renamed.email_verified_at.is_some());
+        assert_eq!(notifier.sent().len(), 1);
+
+        let move_address = UserPatch {
+            email: Some("janet@example.com".to_string()),
+            ..UserPatch::default()
+        };
+        let moved = service
+            .patch_user(
+                tenant,
+                user.id,
+                move_address,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        assert!(moved.email_verified_at.is_none());
+        assert_eq!(notifier.sent().len(), 2);
+        assert_eq!(notifier.sent()[1].to, "janet@example.com");
+    }
+
+    #[tokio::test]
+    async fn update_profile_changes_only_given_fields() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let input = UpdateProfile {
+            name: Some("Janet Doe".to_string()),
+            email: None,
+        };
+
+        let updated = service
+            .update_profile(tenant, user.id, input, &AuditContext::default())
+            .await
+            .unwrap();
+
+        assert_eq!(updated.name, "Janet Doe");
+        assert_eq!(updated.email, "jane@example.com");
+        let roles = service
+            .get_user_roles(tenant, std::slice::from_ref(&updated))
+            .await
+            .unwrap();
+        assert_eq!(roles[&user.id][0].id, role_id);
+    }
+
+    #[tokio::test]
+    async fn update_profile_validates_given_fields() {
+        let (service, tenant, role_id, _) = setup().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let input = UpdateProfile {
+            name: Some(" ".to_string()),
+            email: Some("nope".to_string()),
+        };
+
+        let err = service
+            .update_profile(tenant, user.id, input, &AuditContext::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(field_errors(err), vec!["email", "name"]);
+    }
+
+    #[tokio::test]
+    async fn change_password_requires_current_password() {
+        let (service, tenant, role_id, _, store) = setup_with_store().await;
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let wrong = ChangePassword {
+            current_password: "not-my-password1".to_string(),
+            new_password: "another-secret9".to_string(),
+        };
+        RefreshTokenStore::create(
+            &store,
+            NewRefreshToken {
+                user_id: user.id,
+                family_id: Uuid::new_v4(),
+                token_hash: "session".to_string(),
+                expires_at: chrono::Utc::now().naive_utc() + chrono::Duration::days(1),
+            },
+        )
+        .await
+        .unwrap();
+
+        let err = service
+            .change_password(tenant, user.id, wrong, &AuditContext::default())
+            .await
+            .unwrap_err();
+        assert_eq!(field_errors(err), vec!["current_password"]);
+        let token = store.find_by_hash("session").await.unwrap();
+        assert!(token.revoked_at.is_none());
+
+        let right = ChangePassword {
+            current_password: "secret123".to_string(),
+            new_password: "another-secret9".to_string(),
+        };
+        service
+            .change_password(tenant, user.id, right, &AuditContext::default())
+            .await
+            .unwrap();
+        let stored = service.get_user(tenant, user.id, false).await.unwrap();
+        assert!(bcrypt::verify("another-secret9", &stored.password).unwrap());
+        let token = store.find_by_hash("session").await.unwrap();
+        assert!(token.revoked_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn changes_are_audited_with_secrets_redacted() {
+        let (service, tenant, role_id, _, store) = setup_with_store().await;
+        let ctx = AuditContext {
+            actor_id: Some(Uuid::new_v4()),
+            request_id: Some("req-1".to_string()),
+        };
+        let user = service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &ctx,
+            )
+            .await
+            .unwrap();
+        let input = UserPatch {
+            name: Some("Janet Doe".to_string()),
+            password: Some("another-secret9".to_string()),
+            ..UserPatch::default()
+        };
+        service
+            .patch_user(tenant, user.id, input, &IfMatch::Any, &admin(), &ctx)
+            .await
+            .unwrap();
+
+        let (events, total) = AuditStore::find_all(
+            &store,
+            tenant,
+            &AuditListQuery {
+                entity_id: Some(user.id),
+                ..AuditListQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(total, 2);
+        let update = &events[0];
+        assert_eq!(update.action, "update");
+        assert_eq!(update.actor_id, ctx.actor_id);
+        assert_eq!(update.request_id.as_deref(), Some("req-1"));
+        assert_eq!(
+            update.changes,
+            serde_json::json!({
+                "name": { "before": "Jane Doe", "after": "Janet Doe" },
+                "password": { "before": "[redacted]", "after": "[redacted]" },
+            })
+        );
+        assert_eq!(events[1].action, "create");
+    }
+
+    #[tokio::test]
+    async fn failed_write_is_not_audited() {
+        let (service, tenant, role_id, _, store) = setup_with_store().await;
+        service
+            .create_user(
+                tenant,
+                new_user("jane@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let user = service
+            .create_user(
+                tenant,
+                new_user("john@example.com", role_id),
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let input = UserPatch {
+            email: Some("jane@example.com".to_string()),
+            ..UserPatch::default()
+        };
+
+        let err = service
+            .patch_user(
+                tenant,
+                user.id,
+                input,
+                &IfMatch::Any,
+                &admin(),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, UserError::Conflict { .. }));
+        let (_, total) = AuditStore::find_all(
+            &store,
+            tenant,
+            &AuditListQuery {
+                action: Some("update".to_string()),
+                ..AuditListQuery::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 0);
+    }
+
+    #[tokio::test]
+    async fn failed_transaction_rolls_back_every_write() {
+        let (service, tenant, role_id, _, store) = setup_with_store().await;
+
+        let result = in_transaction(&store, |tx| async move {
+            let user = tx
+                .users()
+                .create_user(tenant, new_user("jane@example.com", role_id))
+                .await?;
+            tx.audit()
+                .record(
+                    tenant,
+                    AuditContext::default().event(AuditAction::Create, None, &user),
+                )
+                .await?;
+            Err::<(), _>(UserError::DatabaseError("later step failed".to_string()))
         })
+        .await;
+
+        assert!(matches!(result, Err(UserError::DatabaseError(_))));
+        let (users, _) = service
+            .get_users(tenant, &UserListQuery::default())
+            .await
+            .unwrap();
+        assert!(users.is_empty());
+        let (_, events) = AuditStore::find_all(&store, tenant, &AuditListQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(events, 0);
     }
 }
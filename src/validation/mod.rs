@@ -0,0 +1,105 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Maximum length of the `varchar(250)` columns.
+pub const MAX_VARCHAR_LENGTH: usize = 250;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// bcrypt silently ignores everything after the 72nd byte.
+pub const MAX_PASSWORD_BYTES: usize = 72;
+
+/// Validation failures keyed by field name, serialized as
+/// `{ "field": ["message", ...] }`.
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationErrors(BTreeMap<String, Vec<String>>);
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0
+            .entry(field.to_string())
+            .or_default()
+            .push(message.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// Checks that `value` is not blank and fits within `max` characters.
+pub fn check_text(errors: &mut ValidationErrors, field: &str, value: &str, max: usize) {
+    if value.trim().is_empty() {
+        errors.add(field, format!("{} is required", field));
+    } else if value.chars().count() > max {
+        errors.add(
+            field,
+            format!("{} must be at most {} characters", field, max),
+        );
+    }
+}
+
+/// Checks that `value` is a single token without whitespace, such as a role
+/// or permission code.
+pub fn check_code(errors: &mut ValidationErrors, field: &str, value: &str) {
+    check_text(errors, field, value, MAX_VARCHAR_LENGTH);
+    if value.chars().any(char::is_whitespace) {
+        errors.add(field, format!("{} must not contain whitespace", field));
+    }
+}
+
+pub fn check_email(errors: &mut ValidationErrors, field: &str, value: &str) {
+    check_text(errors, field, value, MAX_VARCHAR_LENGTH);
+    if value.trim().is_empty() {
+        return;
+    }
+
+    let valid = match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !value.chars().any(char::is_whitespace)
+        }
+        None => false,
+    };
+    if !valid {
+        errors.add(field, format!("{} must be a valid email address", field));
+    }
+}
+
+pub fn check_password(errors: &mut ValidationErrors, field: &str, value: &str) {
+    if value.chars().count() < MIN_PASSWORD_LENGTH {
+        errors.add(
+            field,
+            format!(
+                "{} must be at least {} characters",
+                field, MIN_PASSWORD_LENGTH
+            ),
+        );
+    }
+    if value.len() > MAX_PASSWORD_BYTES {
+        errors.add(
+            field,
+            format!("{} must be at most {} bytes", field, MAX_PASSWORD_BYTES),
+        );
+    }
+    if !value.chars().any(char::is_alphabetic) || !value.chars().any(|c| c.is_ascii_digit()) {
+        errors.add(
+            field,
+            format!("{} must contain at least one letter and one digit", field),
+        );
+    }
+}